@@ -0,0 +1,66 @@
+use serde::Deserialize;
+
+/// How a backend is picked when more than one is configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendStrategy {
+    RoundRobin,
+    /// The backend this limbo has sent the fewest players to since it started. Players are
+    /// handed off through the proxy by server name, so the limbo can't see them leave a
+    /// backend or ping it for its player count. This balances logins, not who is online.
+    /// `least_connections` is accepted as an older name for it.
+    #[serde(alias = "least_connections")]
+    LeastForwarded,
+    /// Always sends the same username to the same backend (while the list is unchanged).
+    Sticky,
+}
+
+/// Picks the backend authenticated players get sent to.
+#[derive(Debug, Default)]
+pub struct BackendSelector {
+    next: usize,
+    /// Players sent to each backend, by index into the configured list. The limbo can't
+    /// see players leave a backend, so this only ever grows.
+    sent: Vec<u64>,
+}
+
+impl BackendSelector {
+    pub fn select<'a>(
+        &mut self,
+        servers: &'a [String],
+        strategy: BackendStrategy,
+        username: &str,
+    ) -> Option<&'a str> {
+        if servers.is_empty() {
+            return None;
+        }
+        self.sent.resize(servers.len(), 0);
+
+        let index = match strategy {
+            BackendStrategy::RoundRobin => {
+                let index = self.next % servers.len();
+                self.next = index + 1;
+                index
+            }
+            BackendStrategy::LeastForwarded => self
+                .sent
+                .iter()
+                .enumerate()
+                .min_by_key(|&(_, &count)| count)
+                .map(|(index, _)| index)
+                .unwrap_or(0),
+            BackendStrategy::Sticky => (fnv1a(username.as_bytes()) % servers.len() as u64) as usize,
+        };
+
+        self.sent[index] += 1;
+        Some(&servers[index])
+    }
+}
+
+/// FNV-1a, used for sticky selection because it is stable across builds and restarts,
+/// unlike the std `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -0,0 +1,154 @@
+use rand::Rng;
+
+use crate::nbt::CompoundBuilder;
+use crate::protocol::packet::PacketBuilder;
+use crate::protocol::play::PlayIds;
+
+// 1.19.2 registry entries
+const FILLED_MAP_ITEM: i32 = 847;
+
+/// First hotbar slot of the player inventory window.
+const HOTBAR_SLOT: i16 = 36;
+const MAP_ID: i32 = 0;
+const MAP_SIZE: usize = 128;
+
+// Map colors are a base color times four plus a shade, shade 2 being the unshaded one
+const WHITE: u8 = 8 * 4 + 2;
+const GRAY: u8 = 11 * 4 + 2;
+const BLACK: u8 = 29 * 4 + 2;
+
+pub const CODE_LENGTH: usize = 5;
+const GLYPH_SCALE: usize = 4;
+const GLYPH_SPACING: usize = 4;
+
+/// 5×7 glyphs, one row per byte with the leftmost pixel in bit 4. Characters that are easy
+/// to mix up (0/O, 1/I, B/8, G/6, Q, S/5, Z/2) are left out.
+const GLYPHS: [(char, [u8; 7]); 27] = [
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100]),
+];
+
+pub fn generate_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..CODE_LENGTH)
+        .map(|_| GLYPHS[rng.gen_range(0..GLYPHS.len())].0)
+        .collect()
+}
+
+/// Whether `input` is the code the player was shown. Case doesn't matter.
+pub fn matches(code: &str, input: &str) -> bool {
+    code.eq_ignore_ascii_case(input)
+}
+
+/// Draws `code` as 128×128 map color indices, one row after another. Some noise is added
+/// and each character is nudged up or down to make it a little harder to read by machine.
+pub fn render(code: &str) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let mut pixels = vec![WHITE; MAP_SIZE * MAP_SIZE];
+
+    for _ in 0..400 {
+        pixels[rng.gen_range(0..MAP_SIZE * MAP_SIZE)] = GRAY;
+    }
+
+    let glyph_width = 5 * GLYPH_SCALE;
+    let glyph_height = 7 * GLYPH_SCALE;
+    let count = code.chars().count();
+    let width = count * glyph_width + count.saturating_sub(1) * GLYPH_SPACING;
+    let left = MAP_SIZE.saturating_sub(width) / 2;
+    let top = (MAP_SIZE - glyph_height) / 2;
+
+    for (i, c) in code.chars().enumerate() {
+        let Some((_, rows)) = GLYPHS.iter().find(|(glyph, _)| *glyph == c) else {
+            continue;
+        };
+        let x0 = left + i * (glyph_width + GLYPH_SPACING);
+        let y0 = top + rng.gen_range(0..=12) - 6;
+
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..5 {
+                if bits & (0b10000 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..GLYPH_SCALE {
+                    for dx in 0..GLYPH_SCALE {
+                        let (x, y) = (x0 + column * GLYPH_SCALE + dx, y0 + row * GLYPH_SCALE + dy);
+                        if x < MAP_SIZE && y < MAP_SIZE {
+                            pixels[y * MAP_SIZE + x] = BLACK;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+/// Map Data replacing the whole map with `pixels`.
+pub fn map_data_packet(ids: &PlayIds, pixels: &[u8]) -> Vec<u8> {
+    PacketBuilder::new(ids.map_data)
+        .with_var_int(MAP_ID)
+        .with_u8(0) // scale
+        .with_bool(true) // locked
+        .with_bool(false) // has icons
+        .with_u8(MAP_SIZE as u8) // columns
+        .with_u8(MAP_SIZE as u8) // rows
+        .with_u8(0) // x
+        .with_u8(0) // z
+        .with_var_int(pixels.len() as i32)
+        .with_raw_bytes(pixels)
+        .build()
+}
+
+/// Puts the captcha map in the first hotbar slot, or empties the slot.
+pub fn map_item_packet(ids: &PlayIds, present: bool) -> Vec<u8> {
+    let packet = PacketBuilder::new(ids.set_container_slot)
+        .with_u8(0) // window id
+        .with_var_int(0) // state id
+        .with_i16(HOTBAR_SLOT)
+        .with_bool(present);
+
+    if !present {
+        return packet.build();
+    }
+
+    packet
+        .with_var_int(FILLED_MAP_ITEM)
+        .with_u8(1) // count
+        .with_nbt(&CompoundBuilder::new().int("map", MAP_ID).build())
+        .build()
+}
+
+/// Lets the player fly, so they can hold the map in adventure mode without falling.
+pub fn flying_abilities_packet(ids: &PlayIds) -> Vec<u8> {
+    PacketBuilder::new(ids.player_abilities)
+        .with_u8(0x02 | 0x04) // flying, allow flying
+        .with_float(0.05) // flying speed
+        .with_float(0.1) // field of view modifier
+        .build()
+}
@@ -0,0 +1,147 @@
+use std::io::{Cursor, Read};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use anyhow::Result;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::protocol::varint::VarInt;
+
+/// Which way a captured packet went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Serverbound = 0,
+    Clientbound = 1,
+}
+
+/// Writes every packet of one connection to a file, one record per packet:
+///
+/// - direction, one byte (see [`Direction`])
+/// - milliseconds since the Unix epoch, u64 big-endian
+/// - length of what follows, u32 big-endian
+/// - the packet ID VarInt and the packet's fields, without the length prefix
+pub struct Capture {
+    file: Mutex<File>,
+    path: PathBuf,
+}
+
+impl Capture {
+    /// Creates a new capture file for `peer` in `dir`.
+    pub async fn create(dir: impl AsRef<Path>, peer: SocketAddr) -> Result<Self> {
+        let dir = dir.as_ref();
+        tokio::fs::create_dir_all(dir).await?;
+
+        // Colons (IPv6, port) aren't allowed in file names everywhere
+        let name = format!("{}-{}.cap", now_millis()?, peer.to_string().replace([':', '[', ']'], "_"));
+        let path = dir.join(name);
+        let file = File::create(&path).await?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+            path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub async fn record(&self, direction: Direction, packet_id: i32, data: &[u8]) -> Result<()> {
+        let id = VarInt::new(packet_id).to_bytes();
+        let length = u32::try_from(id.len() + data.len())?;
+
+        let mut record = Vec::with_capacity(13 + length as usize);
+        record.push(direction as u8);
+        record.extend_from_slice(&now_millis()?.to_be_bytes());
+        record.extend_from_slice(&length.to_be_bytes());
+        record.extend_from_slice(&id);
+        record.extend_from_slice(data);
+
+        let mut file = self.file.lock().await;
+        file.write_all(&record).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Records a clientbound packet, framed as it is passed to [`crate::protocol::write_packet`].
+    pub async fn record_clientbound(&self, framed: &[u8]) -> Result<()> {
+        let mut reader = Cursor::new(framed);
+        VarInt::read_sync(&mut reader)?; // length
+        let packet_id = VarInt::read_sync(&mut reader)?.into_inner();
+        let start = reader.position() as usize;
+
+        self.record(Direction::Clientbound, packet_id, &framed[start..]).await
+    }
+}
+
+/// A packet read back from a capture file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedPacket {
+    pub direction: Direction,
+    pub timestamp_millis: u64,
+    pub packet_id: i32,
+    pub data: Vec<u8>,
+}
+
+/// Reads every record of a capture file written by [`Capture`].
+pub fn replay(path: impl AsRef<Path>) -> Result<Vec<CapturedPacket>> {
+    let contents = std::fs::read(path)?;
+    let mut reader = Cursor::new(contents.as_slice());
+    let mut packets = Vec::new();
+
+    while (reader.position() as usize) < contents.len() {
+        let mut header = [0u8; 13];
+        reader.read_exact(&mut header)?;
+
+        let direction = match header[0] {
+            0 => Direction::Serverbound,
+            1 => Direction::Clientbound,
+            other => return Err(anyhow!("Invalid packet direction {other} in capture")),
+        };
+        let timestamp_millis = u64::from_be_bytes(header[1..9].try_into()?);
+        let length = u32::from_be_bytes(header[9..13].try_into()?) as u64;
+
+        let mut packet = (&mut reader).take(length);
+        let packet_id = VarInt::read_sync(&mut packet)?.into_inner();
+        let mut data = Vec::new();
+        packet.read_to_end(&mut data)?;
+        if packet.limit() != 0 {
+            return Err(anyhow!("Capture ends in the middle of a packet"));
+        }
+
+        packets.push(CapturedPacket {
+            direction,
+            timestamp_millis,
+            packet_id,
+            data,
+        });
+    }
+
+    Ok(packets)
+}
+
+/// Prints a capture file, one packet per line with its data in hex.
+pub fn print_replay(path: impl AsRef<Path>) -> Result<()> {
+    for packet in replay(path)? {
+        let direction = match packet.direction {
+            Direction::Serverbound => "C->S",
+            Direction::Clientbound => "S->C",
+        };
+        println!(
+            "{} {} {:#04x} {}",
+            packet.timestamp_millis,
+            direction,
+            packet.packet_id,
+            data_encoding::HEXLOWER.encode(&packet.data)
+        );
+    }
+    Ok(())
+}
+
+fn now_millis() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64)
+}
@@ -0,0 +1,152 @@
+use json::JsonValue;
+
+/// Formatting selected by legacy codes, applied to the text that follows them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Style {
+    color: Option<&'static str>,
+    obfuscated: bool,
+    bold: bool,
+    strikethrough: bool,
+    underlined: bool,
+    italic: bool,
+}
+
+impl Style {
+    fn component(&self, text: &str) -> JsonValue {
+        let mut component = json::object! { text: text };
+        if let Some(color) = self.color {
+            component["color"] = color.into();
+        }
+
+        let flags = [
+            ("obfuscated", self.obfuscated),
+            ("bold", self.bold),
+            ("strikethrough", self.strikethrough),
+            ("underlined", self.underlined),
+            ("italic", self.italic),
+        ];
+        for (name, set) in flags {
+            if set {
+                component[name] = true.into();
+            }
+        }
+        component
+    }
+
+    /// Applies a code to the style. Returns false for characters that aren't a code, which
+    /// are then kept as text.
+    fn apply(&mut self, code: char) -> bool {
+        if let Some(color) = color_name(code) {
+            // A color code also clears formatting, like in vanilla
+            *self = Style {
+                color: Some(color),
+                ..Style::default()
+            };
+            return true;
+        }
+
+        match code {
+            'k' => self.obfuscated = true,
+            'l' => self.bold = true,
+            'm' => self.strikethrough = true,
+            'n' => self.underlined = true,
+            'o' => self.italic = true,
+            'r' => *self = Style::default(),
+            _ => return false,
+        }
+        true
+    }
+}
+
+fn color_name(code: char) -> Option<&'static str> {
+    let name = match code {
+        '0' => "black",
+        '1' => "dark_blue",
+        '2' => "dark_green",
+        '3' => "dark_aqua",
+        '4' => "dark_red",
+        '5' => "dark_purple",
+        '6' => "gold",
+        '7' => "gray",
+        '8' => "dark_gray",
+        '9' => "blue",
+        'a' => "green",
+        'b' => "aqua",
+        'c' => "red",
+        'd' => "light_purple",
+        'e' => "yellow",
+        'f' => "white",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Converts text with `&` or `§` color and format codes, e.g. `&cInvalid password`, into a
+/// JSON chat component. Unknown codes and a trailing `&` are kept as literal text.
+pub fn legacy_to_chat_json(text: &str) -> String {
+    json::stringify(legacy_to_component(text))
+}
+
+/// [`legacy_to_chat_json`], for embedding the component in a larger JSON document.
+pub fn legacy_to_component(text: &str) -> JsonValue {
+    let mut components = Vec::new();
+    let mut style = Style::default();
+    let mut current = String::new();
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '&' || c == '§' {
+            if let Some(code) = chars.peek().map(char::to_ascii_lowercase) {
+                let mut next = style;
+                if next.apply(code) {
+                    if !current.is_empty() {
+                        components.push(style.component(&current));
+                        current.clear();
+                    }
+                    style = next;
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        components.push(style.component(&current));
+    }
+
+    match components.len() {
+        0 => json::object! { text: "" },
+        1 => components.remove(0),
+        _ => json::object! { text: "", extra: components },
+    }
+}
+
+/// Replaces each `{name}` in `template` with its value. Braces around anything else are
+/// kept as they are.
+pub fn fill_placeholders(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest.find('}').and_then(|end| {
+            let name = &rest[1..end];
+            values.iter().find(|(key, _)| *key == name).map(|(_, value)| (end, value))
+        });
+        match value {
+            Some((end, value)) => {
+                filled.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
@@ -0,0 +1,53 @@
+use std::ops::RangeInclusive;
+
+use crate::config::VersionConfig;
+
+/// A registry codec and the client protocol versions it is valid for.
+pub struct CodecEntry {
+    pub protocols: RangeInclusive<i32>,
+    pub version_name: &'static str,
+    pub json: &'static str,
+}
+
+/// Embedded registry codecs. Sending a codec to a client of a different version makes it
+/// crash while decoding Login (play), so every supported protocol needs an entry here.
+pub const CODECS: &[CodecEntry] = &[CodecEntry {
+    protocols: 760..=760,
+    version_name: "1.19.2",
+    json: include_str!("registry_codec.json"),
+}];
+
+pub fn codec_for(protocol_version: i32) -> Option<&'static CodecEntry> {
+    CODECS
+        .iter()
+        .find(|entry| entry.protocols.contains(&protocol_version))
+}
+
+/// The codec for a protocol version players are allowed to join with, if any.
+pub fn negotiate(protocol_version: i32, versions: &VersionConfig) -> Option<&'static CodecEntry> {
+    codec_for(protocol_version).filter(|_| versions.allows(protocol_version))
+}
+
+/// Codecs for versions players are allowed to join with, oldest first.
+fn allowed_codecs(versions: &VersionConfig) -> impl Iterator<Item = &'static CodecEntry> + '_ {
+    CODECS
+        .iter()
+        .filter(|entry| entry.protocols.clone().any(|protocol| versions.allows(protocol)))
+}
+
+/// The newest protocol players may join with, advertised to clients that aren't supported
+/// so their server list shows them as outdated.
+pub fn newest_protocol(versions: &VersionConfig) -> Option<i32> {
+    allowed_codecs(versions)
+        .flat_map(|entry| entry.protocols.clone())
+        .filter(|protocol| versions.allows(*protocol))
+        .max()
+}
+
+/// Human readable list of the versions players may join with, for kick messages.
+pub fn supported_versions(versions: &VersionConfig) -> String {
+    allowed_codecs(versions)
+        .map(|entry| entry.version_name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
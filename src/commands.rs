@@ -0,0 +1,533 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::db::Registration;
+use crate::{captcha, chat, email, players, ClientStream, State};
+
+/// What the dispatcher should do once a command has run.
+pub enum CommandOutcome {
+    Done,
+    /// The arguments don't fit the command; the player is shown its usage.
+    InvalidSyntax,
+    Kick(String),
+    /// Kicks with a hint to reconnect once the duration has passed.
+    KickRetry(String, Duration),
+    /// A database call failed. The error is logged and the player kicked.
+    DbError(anyhow::Error),
+}
+
+/// A chat command players can run in the limbo, e.g. `/login`.
+#[async_trait]
+pub trait Command: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// e.g. `/login [password]`.
+    fn usage(&self) -> &'static str;
+
+    /// Number of arguments accepted, checked before `execute` is called.
+    fn arity(&self) -> RangeInclusive<usize> {
+        0..=usize::MAX
+    }
+
+    /// Whether the anti-bot checks (`login.require_movement`, the captcha) have to be
+    /// passed before this command is accepted.
+    fn bot_checked(&self) -> bool {
+        false
+    }
+
+    /// Restricts the command to logged in players listed in `admins`.
+    fn admin_only(&self) -> bool {
+        false
+    }
+
+    async fn execute(&self, state: &mut State, stream: &mut ClientStream, args: &[&str]) -> Result<CommandOutcome>;
+}
+
+#[derive(Clone, Default)]
+pub struct CommandRegistry {
+    commands: HashMap<&'static str, Arc<dyn Command>>,
+}
+
+impl CommandRegistry {
+    /// The commands built into the limbo.
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::default();
+        registry.register(Login);
+        registry.register(Register);
+        registry.register(ChangePassword);
+        registry.register(Logout);
+        registry.register(DeleteAccount);
+        registry.register(TwoFactor);
+        registry.register(Verify);
+        registry.register(Whitelist);
+        registry.register(Broadcast);
+        registry.register(Reload);
+        registry.register(List);
+        registry
+    }
+
+    /// Adds `command`, replacing any command with the same name.
+    pub fn register(&mut self, command: impl Command + 'static) {
+        self.commands.insert(command.name(), Arc::new(command));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Command>> {
+        self.commands.get(name).cloned()
+    }
+}
+
+struct Login;
+
+#[async_trait]
+impl Command for Login {
+    fn name(&self) -> &'static str {
+        "login"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/login [password]"
+    }
+
+    fn arity(&self) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    fn bot_checked(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, state: &mut State, stream: &mut ClientStream, args: &[&str]) -> Result<CommandOutcome> {
+        let result = state.db().await.authenticate(&state.username, args[0]).await;
+
+        match result {
+            Ok(true) => {}
+            Ok(false) => {
+                log::warn!("{} [{}] has specified an incorrect password.", state.username, state.real_address);
+                state.metrics().await.logins_failed_total.inc();
+                return Ok(CommandOutcome::Kick(state.messages().invalid_password.clone()));
+            }
+            Err(e) => return Ok(CommandOutcome::DbError(e)),
+        }
+
+        if state.config.two_factor.enabled {
+            let has_totp = state.db().await.has_totp(&state.username).await;
+            match has_totp {
+                Ok(true) => {
+                    log::info!("{} [{}] has entered the correct password, awaiting a 2FA code.", state.username, state.real_address);
+                    state.awaiting_totp = true;
+                    state
+                        .send_chat(stream, &state.messages().totp_prompt)
+                        .await?;
+                    return Ok(CommandOutcome::Done);
+                }
+                Ok(false) => {}
+                Err(e) => return Ok(CommandOutcome::DbError(e)),
+            }
+        }
+
+        log::info!("{} [{}] has successfully authenticated.", state.username, state.real_address);
+        state.on_authenticated(stream).await?;
+        Ok(CommandOutcome::Done)
+    }
+}
+
+struct Register;
+
+#[async_trait]
+impl Command for Register {
+    fn name(&self) -> &'static str {
+        "register"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/register [password] [password] [email]"
+    }
+
+    fn arity(&self) -> RangeInclusive<usize> {
+        2..=3
+    }
+
+    fn bot_checked(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, state: &mut State, stream: &mut ClientStream, args: &[&str]) -> Result<CommandOutcome> {
+        if args[0] != args[1] {
+            return Ok(CommandOutcome::Kick(state.messages().passwords_do_not_match.clone()));
+        }
+
+        let email = args.get(2).copied();
+        match email {
+            None if state.config.login.require_email => return Ok(CommandOutcome::InvalidSyntax),
+            Some(email) if !email::valid_email(email) => {
+                return Ok(CommandOutcome::Kick(state.messages().invalid_email.clone()));
+            }
+            _ => {}
+        }
+
+        let now = Instant::now();
+        let cooldown = state.context.lock().await.registrations.remaining(&state.real_address, now);
+        if let Some(wait) = cooldown {
+            log::warn!("{} [{}] tried to register during the cooldown.", state.username, state.real_address);
+            let seconds = wait.as_secs_f64().ceil().to_string();
+            return Ok(CommandOutcome::KickRetry(
+                chat::fill_placeholders(&state.messages().registration_cooldown, &[("seconds", &seconds)]),
+                wait,
+            ));
+        }
+
+        let max_accounts = state.config.rate_limit.max_accounts_per_ip;
+        let result = state
+            .db()
+            .await
+            .register(&state.username, args[0], email, Some(&state.real_address), max_accounts)
+            .await;
+        if let Ok(Registration::Created) = result {
+            state.context.lock().await.registrations.record(&state.real_address, now);
+        }
+
+        match result {
+            Ok(Registration::Created) => {
+                log::info!("{} [{}] has successfully registered.", state.username, state.real_address);
+                state.events().await.on_register(&state.username, state.peer).await;
+                if let Some(email) = email {
+                    // Mail can be slow, so it's sent in the background
+                    let notifier = Arc::clone(&state.context.lock().await.notifier);
+                    let (name, email) = (state.username.clone(), email.to_string());
+                    tokio::spawn(async move {
+                        if let Err(e) = notifier.send_confirmation(&name, &email).await {
+                            log::error!("Failed to send the confirmation email to {}: {:?}", name, e);
+                        }
+                    });
+                }
+                state.on_authenticated(stream).await?;
+                Ok(CommandOutcome::Done)
+            }
+            Ok(Registration::AlreadyRegistered) => {
+                log::warn!("{} [{}] attempted double registration.", state.username, state.real_address);
+                Ok(CommandOutcome::Kick(state.messages().already_registered.clone()))
+            }
+            Ok(Registration::TooManyAccounts) => {
+                log::warn!("{} [{}] has too many accounts to register another.", state.username, state.real_address);
+                Ok(CommandOutcome::Kick(state.messages().too_many_accounts.clone()))
+            }
+            Err(e) => Ok(CommandOutcome::DbError(e)),
+        }
+    }
+}
+
+struct ChangePassword;
+
+#[async_trait]
+impl Command for ChangePassword {
+    fn name(&self) -> &'static str {
+        "changepassword"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/changepassword [old password] [new password]"
+    }
+
+    fn arity(&self) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    async fn execute(&self, state: &mut State, stream: &mut ClientStream, args: &[&str]) -> Result<CommandOutcome> {
+        if !state.authenticated {
+            state.send_chat(stream, &state.messages().log_in_first).await?;
+            return Ok(CommandOutcome::Done);
+        }
+
+        let result = state.db().await.change_password(&state.username, args[0], args[1]).await;
+
+        match result {
+            Ok(true) => {
+                log::info!("{} [{}] has changed their password.", state.username, state.real_address);
+                state.send_chat(stream, &state.messages().password_changed).await?;
+            }
+            Ok(false) => {
+                log::warn!("{} [{}] has specified an incorrect password.", state.username, state.real_address);
+                state.send_chat(stream, &state.messages().incorrect_password).await?;
+            }
+            Err(e) => return Ok(CommandOutcome::DbError(e)),
+        }
+        Ok(CommandOutcome::Done)
+    }
+}
+
+struct DeleteAccount;
+
+#[async_trait]
+impl Command for DeleteAccount {
+    fn name(&self) -> &'static str {
+        "deleteaccount"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/deleteaccount [password]"
+    }
+
+    fn arity(&self) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    async fn execute(&self, state: &mut State, stream: &mut ClientStream, args: &[&str]) -> Result<CommandOutcome> {
+        if !state.config.login.allow_account_deletion {
+            state.send_chat(stream, &state.messages().account_deletion_disabled).await?;
+            return Ok(CommandOutcome::Done);
+        }
+        if !state.authenticated {
+            state.send_chat(stream, &state.messages().log_in_first).await?;
+            return Ok(CommandOutcome::Done);
+        }
+
+        let result = state.db().await.delete_account(&state.username, args[0]).await;
+        match result {
+            Ok(true) => {
+                log::info!("{} [{}] has deleted their account.", state.username, state.real_address);
+                Ok(CommandOutcome::Kick(state.messages().account_deleted.clone()))
+            }
+            Ok(false) => {
+                log::warn!("{} [{}] has specified an incorrect password.", state.username, state.real_address);
+                state.send_chat(stream, &state.messages().incorrect_password).await?;
+                Ok(CommandOutcome::Done)
+            }
+            Err(e) => Ok(CommandOutcome::DbError(e)),
+        }
+    }
+}
+
+struct Logout;
+
+#[async_trait]
+impl Command for Logout {
+    fn name(&self) -> &'static str {
+        "logout"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/logout"
+    }
+
+    fn bot_checked(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, state: &mut State, stream: &mut ClientStream, _args: &[&str]) -> Result<CommandOutcome> {
+        if !state.authenticated {
+            state.send_chat(stream, &state.messages().log_in_first).await?;
+            return Ok(CommandOutcome::Done);
+        }
+
+        let result = state.db().await.clear_sessions(&state.username).await;
+        if let Err(e) = result {
+            return Ok(CommandOutcome::DbError(e));
+        }
+
+        log::info!("{} [{}] has cleared their saved sessions.", state.username, state.real_address);
+        state.send_chat(stream, &state.messages().sessions_cleared).await?;
+        Ok(CommandOutcome::Done)
+    }
+}
+
+struct TwoFactor;
+
+#[async_trait]
+impl Command for TwoFactor {
+    fn name(&self) -> &'static str {
+        "2fa"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/2fa enable [password] or /2fa [code]"
+    }
+
+    fn bot_checked(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, state: &mut State, stream: &mut ClientStream, args: &[&str]) -> Result<CommandOutcome> {
+        if !state.config.two_factor.enabled {
+            state
+                .send_chat(stream, &state.messages().totp_unavailable)
+                .await?;
+            return Ok(CommandOutcome::Done);
+        }
+
+        match args {
+            ["enable", _] if !state.authenticated => {
+                state.send_chat(stream, &state.messages().log_in_first).await?;
+            }
+            ["enable", password] => state.begin_totp_setup(stream, password).await?,
+            [code] => state.submit_totp_code(stream, code).await?,
+            _ => return Ok(CommandOutcome::InvalidSyntax),
+        }
+        Ok(CommandOutcome::Done)
+    }
+}
+
+struct Verify;
+
+#[async_trait]
+impl Command for Verify {
+    fn name(&self) -> &'static str {
+        "verify"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/verify [code]"
+    }
+
+    fn arity(&self) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    async fn execute(&self, state: &mut State, stream: &mut ClientStream, args: &[&str]) -> Result<CommandOutcome> {
+        let Some(code) = &state.captcha else {
+            state.send_chat(stream, &state.messages().nothing_to_verify).await?;
+            return Ok(CommandOutcome::Done);
+        };
+
+        if !captcha::matches(code, args[0]) {
+            log::warn!("{} [{}] entered the wrong captcha code.", state.username, state.real_address);
+            return Ok(CommandOutcome::Kick(state.messages().wrong_captcha_code.clone()));
+        }
+
+        log::info!("{} [{}] has solved the captcha.", state.username, state.real_address);
+        state.captcha = None;
+        state.send_packet(stream, captcha::map_item_packet(state.ids, false)).await?;
+        state.send_chat(stream, &state.messages().captcha_solved).await?;
+        Ok(CommandOutcome::Done)
+    }
+}
+
+struct Whitelist;
+
+#[async_trait]
+impl Command for Whitelist {
+    fn name(&self) -> &'static str {
+        "whitelist"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/whitelist add|remove [name]"
+    }
+
+    fn admin_only(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, state: &mut State, stream: &mut ClientStream, args: &[&str]) -> Result<CommandOutcome> {
+        let (action, name) = match args {
+            [action @ ("add" | "remove"), name] => (*action, *name),
+            _ => return Ok(CommandOutcome::InvalidSyntax),
+        };
+
+        let db = state.db().await;
+        let result = match action {
+            "add" => db.whitelist_add(name).await,
+            _ => db.whitelist_remove(name).await,
+        };
+        if let Err(e) = result {
+            return Ok(CommandOutcome::DbError(e));
+        }
+
+        log::info!("{} [{}] ran /whitelist {} {}.", state.username, state.real_address, action, name);
+        let message = chat::fill_placeholders(&state.messages().whitelist_updated, &[("action", action), ("name", name)]);
+        state.send_chat(stream, &message).await?;
+        Ok(CommandOutcome::Done)
+    }
+}
+
+struct Broadcast;
+
+#[async_trait]
+impl Command for Broadcast {
+    fn name(&self) -> &'static str {
+        "broadcast"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/broadcast [message]"
+    }
+
+    fn arity(&self) -> RangeInclusive<usize> {
+        1..=usize::MAX
+    }
+
+    fn admin_only(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, state: &mut State, _stream: &mut ClientStream, args: &[&str]) -> Result<CommandOutcome> {
+        let message = args.join(" ");
+        let count = players::broadcast(&state.players().await, &message).await;
+
+        log::info!("{} [{}] broadcast \"{}\" to {} players.", state.username, state.real_address, message, count);
+        Ok(CommandOutcome::Done)
+    }
+}
+
+struct Reload;
+
+#[async_trait]
+impl Command for Reload {
+    fn name(&self) -> &'static str {
+        "reload"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/reload"
+    }
+
+    fn arity(&self) -> RangeInclusive<usize> {
+        0..=0
+    }
+
+    fn admin_only(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, state: &mut State, stream: &mut ClientStream, _args: &[&str]) -> Result<CommandOutcome> {
+        let result = state.context.lock().await.reload_config();
+        match result {
+            Ok(()) => {
+                log::info!("{} [{}] reloaded the config.", state.username, state.real_address);
+                state.send_chat(stream, &state.messages().config_reloaded).await?;
+            }
+            Err(e) => {
+                log::error!("Failed to reload the config: {:?}", e);
+                state.send_chat(stream, &state.messages().config_reload_failed).await?;
+            }
+        }
+        Ok(CommandOutcome::Done)
+    }
+}
+
+struct List;
+
+#[async_trait]
+impl Command for List {
+    fn name(&self) -> &'static str {
+        "list"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/list"
+    }
+
+    async fn execute(&self, state: &mut State, stream: &mut ClientStream, _args: &[&str]) -> Result<CommandOutcome> {
+        let online = players::online(&state.players().await).await;
+        let names = online.into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+        let count = names.len().to_string();
+        let message =
+            chat::fill_placeholders(&state.messages().player_list, &[("count", &count), ("names", &names.join(", "))]);
+        state.send_chat(stream, &message).await?;
+        Ok(CommandOutcome::Done)
+    }
+}
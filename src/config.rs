@@ -0,0 +1,635 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use anyhow::anyhow;
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::backend::BackendStrategy;
+use crate::messages::Localized;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Addresses to listen on, e.g. `0.0.0.0:25565` or `[::]:25565`. On most systems `[::]`
+    /// also accepts IPv4, so it can't be combined with `0.0.0.0` on the same port. An
+    /// address passed on the command line replaces these.
+    pub bind: Vec<String>,
+    /// Players allowed to use admin commands once logged in.
+    pub admins: Vec<String>,
+    pub database: DbConfig,
+    /// Drop connections that send a packet the server doesn't expect during status or
+    /// login. Play stays tolerant either way.
+    pub strict_protocol: bool,
+    /// Write every packet of every connection to a file in this directory, for debugging.
+    /// Read them back with `./void-rs --replay [file]`.
+    pub capture_dir: Option<String>,
+    pub motd: MotdConfig,
+    pub void: VoidConfig,
+    pub login: LoginConfig,
+    pub versions: VersionConfig,
+    pub rate_limit: RateLimitConfig,
+    pub two_factor: TwoFactorConfig,
+    pub sessions: SessionConfig,
+    pub hashing: HashingConfig,
+    pub argon2: Argon2Config,
+    pub whitelist: WhitelistConfig,
+    pub captcha: CaptchaConfig,
+    pub rcon: RconConfig,
+    pub metrics: MetricsConfig,
+    pub health: HealthConfig,
+    pub forge: ForgeConfig,
+    pub proxy_protocol: ProxyProtocolConfig,
+    pub backend: BackendConfig,
+    pub ping_passthrough: PingPassthroughConfig,
+    pub world: WorldConfig,
+    pub resource_pack: Option<ResourcePackConfig>,
+    /// Chat lines sent when a player spawns, before they are asked to log in, e.g. a short
+    /// tutorial. `&` color codes work.
+    pub welcome_lines: Vec<String>,
+    /// Milliseconds between two welcome lines. The login prompt waits for the last one.
+    pub welcome_delay_ms: u64,
+    /// Locale whose messages are shown when there is no messages file for the client's
+    /// locale, `en` if unset.
+    pub default_locale: Option<String>,
+    /// Read from the `messages.<locale>.toml` files in the same directory as the config file.
+    #[serde(skip)]
+    pub messages: Localized,
+}
+
+/// Where player accounts are stored.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct DbConfig {
+    #[serde(flatten)]
+    pub engine: DbEngine,
+    pub namespace: String,
+    pub database: String,
+    /// Times a call that failed for a passing reason, like a busy RocksDB or a dropped
+    /// connection, is tried again before the player sees a database error.
+    pub retries: u32,
+    /// Wait before the first retry in milliseconds, doubled for every retry after it.
+    pub retry_delay_ms: u64,
+    /// How long players kicked for a database error are told to wait before reconnecting.
+    /// 0 leaves the hint out.
+    pub reconnect_after_secs: u64,
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        Self {
+            engine: DbEngine::RocksDb {
+                path: String::from("./database"),
+            },
+            namespace: String::from("void"),
+            database: String::from("credentials"),
+            retries: 3,
+            retry_delay_ms: 50,
+            reconnect_after_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "engine", rename_all = "lowercase")]
+pub enum DbEngine {
+    /// On-disk store in the `path` directory.
+    RocksDb { path: String },
+    /// Kept in memory and lost on restart. Meant for testing.
+    Mem,
+    /// A SurrealDB server, e.g. `ws://127.0.0.1:8000`. Signs in as a root user when
+    /// `username` is set.
+    Ws {
+        url: String,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+    },
+}
+
+/// The server list entry. `{online}`, `{max}` and `{version}` in the lines are filled in
+/// for every status request.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MotdConfig {
+    /// One or two lines. Each starts out white, so its `&` color codes don't carry over
+    /// to the next line.
+    pub lines: Vec<String>,
+    /// Player limit shown in the list. The limbo itself doesn't enforce it.
+    pub max_players: usize,
+}
+
+impl Default for MotdConfig {
+    fn default() -> Self {
+        Self {
+            lines: vec![String::from("&bvoid-rs"), String::from("&7{online} online")],
+            max_players: 100,
+        }
+    }
+}
+
+/// What to do with a player whose reported Y drops below `threshold`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VoidAction {
+    Teleport,
+    Kick,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct VoidConfig {
+    pub threshold: f64,
+    pub action: VoidAction,
+    pub kick_message: String,
+    /// Put players back at spawn every second while they are falling, rather than only
+    /// once they pass `threshold`.
+    pub keep_still: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoginConfig {
+    /// Seconds a player has to /login or /register before being kicked. 0 disables the limit.
+    pub timeout_secs: u64,
+    /// Seconds after joining the limbo after which a player who hasn't logged in yet is
+    /// logged as slow, which points to bots and stuck clients. 0 disables the warning.
+    pub slow_login_secs: u64,
+    /// Ignore /login and /register until the player has moved or swung their arm, which
+    /// simple bots don't do.
+    pub require_movement: bool,
+    /// Make the email address in `/register [password] [password] [email]` mandatory
+    /// rather than optional.
+    pub require_email: bool,
+    /// Let players delete their own account with `/deleteaccount [password]`.
+    pub allow_account_deletion: bool,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            slow_login_secs: 15,
+            require_movement: false,
+            require_email: false,
+            allow_account_deletion: true,
+        }
+    }
+}
+
+/// Narrows the protocol versions players may join with. Versions without an embedded
+/// registry codec are refused regardless.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct VersionConfig {
+    pub min_protocol: Option<i32>,
+    pub max_protocol: Option<i32>,
+}
+
+impl VersionConfig {
+    pub fn allows(&self, protocol_version: i32) -> bool {
+        self.min_protocol.is_none_or(|min| protocol_version >= min)
+            && self.max_protocol.is_none_or(|max| protocol_version <= max)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Connections allowed per IP within `window_secs`. 0 disables the limit. Behind a
+    /// proxy every connection comes from the proxy's IP, so this only works with direct
+    /// exposure or with `proxy_protocol` passing on the real address.
+    pub connections: usize,
+    pub window_secs: u64,
+    /// Connections open at once across all IPs; new ones are dropped beyond this. 0
+    /// disables the limit.
+    pub max_connections: usize,
+    /// Connections that may wait for a slot once `max_connections` is reached, instead of
+    /// being dropped. Waiting players spawn in the limbo and are shown their place in line,
+    /// then get the login prompt once a slot frees up. 0 disables the queue.
+    pub queue_size: usize,
+    /// Seconds a connection may spend in the handshake and status states, e.g. a server
+    /// list ping that never sends its ping or a port scanner that sends nothing, before it
+    /// is dropped to free its slot. 0 disables the limit.
+    pub status_timeout_secs: u64,
+    /// Seconds before another account can be registered from the same IP. 0 disables the
+    /// cooldown.
+    pub registration_cooldown_secs: u64,
+    /// Accounts that can be registered from the same IP in total. 0 disables the limit.
+    pub max_accounts_per_ip: usize,
+    /// Packets per second a connection may send on average, keep alives aside. Going over
+    /// gets it kicked. 0 disables the limit.
+    pub packets_per_sec: u32,
+    /// Packets a connection may send at once before `packets_per_sec` applies.
+    pub packet_burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            connections: 0,
+            window_secs: 10,
+            max_connections: 1000,
+            queue_size: 0,
+            status_timeout_secs: 5,
+            registration_cooldown_secs: 60,
+            max_accounts_per_ip: 0,
+            packets_per_sec: 100,
+            packet_burst: 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct TwoFactorConfig {
+    /// Allow players to protect their account with TOTP codes (`/2fa`).
+    pub enabled: bool,
+    /// Passphrase the stored TOTP secrets are encrypted with. Changing it makes every
+    /// existing secret unreadable.
+    pub encryption_key: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Log players back in without a password when they reconnect from the same IP.
+    pub enabled: bool,
+    pub ttl_secs: u64,
+    /// Seconds a player with a session waits in the limbo before being logged in, so the
+    /// client has finished loading when it's sent on. Checked once a second.
+    pub forward_delay_secs: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: 12 * 60 * 60,
+            forward_delay_secs: 1,
+        }
+    }
+}
+
+/// Scheme new password hashes are created with. Hashes from the other scheme are still
+/// accepted, and replaced the next time their owner logs in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Argon2,
+    Bcrypt,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct HashingConfig {
+    pub algorithm: HashAlgorithm,
+    pub bcrypt_cost: u32,
+}
+
+impl Default for HashingConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: HashAlgorithm::Argon2,
+            bcrypt_cost: 12,
+        }
+    }
+}
+
+/// Argon2 cost. Existing hashes are upgraded the next time their owner logs in.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Argon2Config {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /// File holding a secret (pepper) mixed into every Argon2 hash, so a copy of the
+    /// database alone isn't enough to guess passwords offline. Without it, the
+    /// `VOID_ARGON2_PEPPER` environment variable is used if set. Adding, changing or
+    /// removing the pepper makes every existing Argon2 hash fail to verify.
+    pub pepper_file: Option<String>,
+}
+
+impl Default for Argon2Config {
+    fn default() -> Self {
+        Self {
+            memory_kib: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+            parallelism: argon2::Params::DEFAULT_P_COST,
+            pepper_file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProxyProtocolConfig {
+    /// Expect a PROXY protocol v2 header on every connection, as sent by HAProxy and most
+    /// TCP load balancers. Connections without a valid header are dropped.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ForgeConfig {
+    /// Let Forge clients in. Their mod negotiation is ignored, so only clients whose mods
+    /// are optional on the server side will get past the login screen.
+    pub allow: bool,
+    pub kick_message: String,
+}
+
+impl Default for ForgeConfig {
+    fn default() -> Self {
+        Self {
+            allow: false,
+            kick_message: String::from("Modded clients are not supported. Please connect using vanilla Minecraft."),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Serve Prometheus metrics over HTTP at `/metrics`.
+    pub enabled: bool,
+    pub bind: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: String::from("127.0.0.1:9225"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Answer `GET /health` over HTTP for liveness and readiness probes: 200 with a JSON
+    /// body while the database works, 503 otherwise.
+    pub enabled: bool,
+    pub bind: String,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: String::from("127.0.0.1:9226"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CaptchaConfig {
+    /// Hand players a map showing a code they have to enter with `/verify` before they can
+    /// log in or register. They join in adventure mode instead of spectator so they can
+    /// hold it, and are left flying so they don't fall.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WhitelistConfig {
+    /// Only let in players added with `/whitelist add`. Admins are always let in.
+    pub enabled: bool,
+    pub kick_message: String,
+}
+
+impl Default for WhitelistConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            kick_message: String::from("&cYou are not whitelisted on this server."),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RconConfig {
+    /// Accept remote admin commands (`list`, `ban`, `kick`, `whitelist`, `broadcast`, `reload`) over RCON.
+    pub enabled: bool,
+    pub bind: String,
+    /// Required when `enabled` is set.
+    pub password: String,
+}
+
+impl Default for RconConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: String::from("127.0.0.1:25575"),
+            password: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BackendConfig {
+    /// Send authenticated players to one of `servers` through the proxy. When off they stay
+    /// in the limbo.
+    pub transfer: bool,
+    pub servers: Vec<String>,
+    pub strategy: BackendStrategy,
+    pub welcome_message: String,
+    /// Name of the limbo as the proxy knows it, reported to BungeeCord GetServer requests.
+    pub server_name: String,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        Self {
+            transfer: true,
+            servers: vec![String::from("main")],
+            strategy: BackendStrategy::RoundRobin,
+            welcome_message: String::from("Welcome, you are now authenticated."),
+            server_name: String::from("limbo"),
+        }
+    }
+}
+
+/// Answers status requests with the status of a backend server, so the server list shows
+/// its version and player counts instead of the limbo's.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PingPassthroughConfig {
+    pub enabled: bool,
+    /// Address of the backend itself, not of the proxy in front of it.
+    pub address: String,
+    /// Seconds a fetched status, or a failure to fetch it, is reused for.
+    pub cache_secs: u64,
+    /// How long to wait for the backend before answering with the limbo's own status.
+    pub timeout_ms: u64,
+}
+
+impl Default for PingPassthroughConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: String::from("127.0.0.1:25566"),
+            cache_secs: 5,
+            timeout_ms: 1000,
+        }
+    }
+}
+
+/// Blocks sent to players in the limbo, which is otherwise empty. Players spawn at X 0,
+/// Y 0, Z 0 in the End, which spans Y 0 to 255.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WorldConfig {
+    pub platforms: Vec<PlatformConfig>,
+    /// Chunks sent in each direction around the one at spawn, so 2 sends 5×5 chunks.
+    /// Also reported to the client as its view and simulation distance.
+    pub view_distance: i32,
+}
+
+/// A box of a single block, from its lowest corner.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlatformConfig {
+    /// Block state ID in the client's version, e.g. 1 for stone in 1.19.2.
+    pub block: i32,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    /// Size along X.
+    pub width: i32,
+    /// Size along Y.
+    pub height: i32,
+    /// Size along Z.
+    pub length: i32,
+}
+
+/// Server resource pack offered to players once they join the limbo.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResourcePackConfig {
+    pub url: String,
+    /// Lowercase hex SHA-1 of the pack, so the client can reuse a cached copy.
+    pub hash: String,
+    /// Kick players who decline the pack or fail to download it.
+    #[serde(default)]
+    pub forced: bool,
+    /// Extra text shown in the client's download prompt. May use `&` color codes.
+    #[serde(default)]
+    pub prompt: Option<String>,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            platforms: Vec::new(),
+            view_distance: 2,
+        }
+    }
+}
+
+impl Default for VoidConfig {
+    fn default() -> Self {
+        Self {
+            threshold: -64.0,
+            action: VoidAction::Teleport,
+            kick_message: String::from("You fell out of the world."),
+            keep_still: false,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config from `path`, falling back to the defaults if the file doesn't exist,
+    /// along with the messages files next to it.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        if !path.exists() {
+            log::info!("{} not found, using the default configuration.", path.display());
+            return Ok(Self {
+                messages: Localized::load(dir, "en")?,
+                ..Self::default()
+            });
+        }
+
+        let contents = std::fs::read_to_string(path)?;
+        let mut config: Config = toml::from_str(&contents)?;
+        config.messages = Localized::load(dir, config.default_locale.as_deref().unwrap_or("en"))?;
+
+        if config.two_factor.enabled && config.two_factor.encryption_key.is_empty() {
+            return Err(anyhow!("two_factor.encryption_key must be set when two-factor authentication is enabled"));
+        }
+
+        if config.motd.lines.len() > 2 {
+            return Err(anyhow!("motd.lines can't have more than two lines"));
+        }
+
+        if !(0..=32).contains(&config.world.view_distance) {
+            return Err(anyhow!("world.view_distance must be between 0 and 32"));
+        }
+
+        if config.rcon.enabled && config.rcon.password.is_empty() {
+            return Err(anyhow!("rcon.password must be set when RCON is enabled"));
+        }
+
+        Ok(config)
+    }
+
+    /// Settings that differ from `other` but are only read at startup.
+    fn restart_required(&self, other: &Config) -> Vec<&'static str> {
+        [
+            ("bind", self.bind != other.bind),
+            ("database", self.database != other.database),
+            ("two_factor", self.two_factor != other.two_factor),
+            ("hashing", self.hashing != other.hashing),
+            ("argon2", self.argon2 != other.argon2),
+            ("rcon", self.rcon != other.rcon),
+            ("metrics", self.metrics != other.metrics),
+            ("health", self.health != other.health),
+            ("rate_limit.max_connections", self.rate_limit.max_connections != other.rate_limit.max_connections),
+            ("rate_limit.queue_size", self.rate_limit.queue_size != other.rate_limit.queue_size),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+}
+
+/// The config new connections start with, swapped out as a whole by `/reload`. Connections
+/// already open keep the config they started with.
+pub struct SharedConfig {
+    path: PathBuf,
+    current: RwLock<Arc<Config>>,
+}
+
+impl SharedConfig {
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let config = Config::load(&path)?;
+        Ok(Self {
+            path,
+            current: RwLock::new(Arc::new(config)),
+        })
+    }
+
+    pub fn get(&self) -> Arc<Config> {
+        Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Re-reads the config file and swaps it in, unless it fails to load. Changes to
+    /// settings only read at startup are logged and otherwise ignored.
+    pub fn reload(&self) -> Result<Arc<Config>> {
+        let config = Arc::new(Config::load(&self.path)?);
+
+        for name in self.get().restart_required(&config) {
+            log::warn!("Ignoring the change to `{}` until the next restart.", name);
+        }
+
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::clone(&config);
+        log::info!("Reloaded {}.", self.path.display());
+        Ok(config)
+    }
+}
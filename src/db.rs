@@ -1,29 +1,138 @@
-use argon2::password_hash::rand_core::OsRng;
-use argon2::password_hash::SaltString;
-use argon2::Argon2;
-use argon2::PasswordHash;
-use argon2::PasswordVerifier;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use surrealdb::error::{Api, Db};
 use surrealdb::RecordId;
 use surrealdb::Surreal;
-use argon2::PasswordHasher;
 
-use surrealdb::engine::local::RocksDb;
+use surrealdb::engine::any::{self, Any};
+use surrealdb::opt::auth::Root;
 
-use crate::Context;
+use crate::config::{Config, DbConfig, DbEngine};
+use crate::hashing::PasswordHashing;
+use crate::totp;
 
-pub async fn init_db() -> surrealdb::Result<Surreal<surrealdb::engine::local::Db>> {
-    let db = Surreal::new::<RocksDb>("./database").await?;
+pub async fn init_db(config: &DbConfig) -> surrealdb::Result<Surreal<Any>> {
+    let db = match &config.engine {
+        DbEngine::RocksDb { path } => any::connect(format!("rocksdb://{path}")).await?,
+        DbEngine::Mem => any::connect("mem://").await?,
+        DbEngine::Ws { url, username, password } => {
+            let db = any::connect(url).await?;
+            if let Some(username) = username {
+                db.signin(Root {
+                    username,
+                    password: password.as_deref().unwrap_or_default(),
+                })
+                .await?;
+            }
+            db
+        }
+    };
 
-    db.use_ns("void").use_db("credentials").await?;
+    db.use_ns(&config.namespace).use_db(&config.database).await?;
 
     Ok(db)
 }
 
+/// The database together with what it takes to use it. Shared as an `Arc` and used without
+/// holding the `Context` lock, so a slow query or a retry backoff only holds up the
+/// connection waiting for it.
+pub struct Database {
+    db: Surreal<Any>,
+    retry: RetryPolicy,
+    hashing: PasswordHashing,
+    /// Encryption key for stored TOTP secrets, set when two-factor authentication is enabled.
+    totp_key: Option<[u8; 32]>,
+}
+
+/// How database calls that fail for a passing reason, like a busy RocksDB or a dropped
+/// connection, are tried again before the error reaches the player.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    /// Wait before the first retry, doubled for every retry after it.
+    pub initial_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(config: &DbConfig) -> Self {
+        Self {
+            retries: config.retries,
+            initial_delay: Duration::from_millis(config.retry_delay_ms),
+        }
+    }
+
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> surrealdb::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = surrealdb::Result<T>>,
+    {
+        let mut delay = self.initial_delay;
+        let mut attempt = 0;
+        loop {
+            match operation().await {
+                Err(e) if attempt < self.retries && is_transient(&e) => {
+                    attempt += 1;
+                    log::warn!("Database call failed, retrying in {:?} ({}/{}): {}", delay, attempt, self.retries, e);
+                    tokio::time::sleep(delay).await;
+                    delay = delay.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Errors from the storage engine or the connection to it, as opposed to ones caused by the
+/// query itself, which would fail the same way again.
+fn is_transient(error: &surrealdb::Error) -> bool {
+    matches!(
+        error,
+        surrealdb::Error::Db(Db::Ds(_) | Db::Tx(_) | Db::TxFailure | Db::QueryTimedout)
+            | surrealdb::Error::Api(Api::Ws(_) | Api::Http(_))
+    )
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Credentials {
     name: String,
     hash: String,
+    /// Encrypted TOTP secret, present once the player has enabled two-factor authentication.
+    #[serde(default)]
+    totp: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+    /// Address the account was registered from. Accounts from before this was stored have none.
+    #[serde(default)]
+    ip: Option<String>,
+}
+
+/// A remembered login, letting `name` back in from `ip` until `expires` (unix seconds).
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    name: String,
+    ip: String,
+    expires: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Ban {
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WhitelistEntry {
+    name: String,
+}
+
+/// Outcome of [`Database::register`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registration {
+    Created,
+    AlreadyRegistered,
+    /// The address already has `max_accounts_per_ip` accounts.
+    TooManyAccounts,
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,53 +141,371 @@ struct Record {
     id: RecordId,
 }
 
-impl Context {
+impl Database {
+    /// Opens the database and sets up password hashing and TOTP encryption.
+    pub async fn new(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            db: init_db(&config.database).await?,
+            retry: RetryPolicy::new(&config.database),
+            hashing: PasswordHashing::new(&config.hashing, &config.argon2)?,
+            totp_key: config
+                .two_factor
+                .enabled
+                .then(|| totp::derive_key(&config.two_factor.encryption_key)),
+        })
+    }
+
+    /// Fails when the database can't be reached or can't run a query.
+    pub async fn check_db(&self) -> anyhow::Result<()> {
+        self.db.health().await?;
+        self.db.query("INFO FOR DB").await?.check()?;
+        Ok(())
+    }
+
     pub async fn player_exists(&self, name: &str) -> anyhow::Result<bool> {
-        let users: Vec<Credentials> = self.db.select("credentials").await?;
+        let users: Vec<Credentials> = self.retry.run(|| async { self.db.select("credentials").await }).await?;
         let user = users.iter().find(|a| a.name == name);
         Ok(user.is_some())
     }
 
-    pub async fn register(&self, name: &str, password: &str) -> anyhow::Result<bool> {
-        if self.player_exists(&name).await? {
-            return Ok(false);
+    /// Which of `names` have an account, checked with a single query.
+    pub async fn players_exist(&self, names: &[&str]) -> anyhow::Result<HashMap<String, bool>> {
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        let found: Vec<String> = self
+            .retry
+            .run(|| async {
+                self.db
+                    .query("SELECT name FROM credentials WHERE name IN $names")
+                    .bind(("names", names.clone()))
+                    .await?
+                    .take("name")
+            })
+            .await?;
+
+        Ok(names
+            .into_iter()
+            .map(|name| {
+                let exists = found.contains(&name);
+                (name, exists)
+            })
+            .collect())
+    }
+
+    /// Creates an account registered from `ip`, unless that address already has
+    /// `max_accounts_per_ip` accounts. 0 allows any number. Accounts created without a
+    /// client, e.g. from the command line, have no address.
+    pub async fn register(
+        &self,
+        name: &str,
+        password: &str,
+        email: Option<&str>,
+        ip: Option<&str>,
+        max_accounts_per_ip: usize,
+    ) -> anyhow::Result<Registration> {
+        if self.player_exists(name).await? {
+            return Ok(Registration::AlreadyRegistered);
         }
 
-        let argon2 = Argon2::default();
-        let salt = SaltString::generate(&mut OsRng);
-        let hash = argon2.hash_password(password.as_bytes(), &salt)?;
-        let hash = hash.serialize().to_string();
+        if let Some(ip) = ip.filter(|_| max_accounts_per_ip > 0) {
+            if self.accounts_for_ip(ip).await? >= max_accounts_per_ip {
+                return Ok(Registration::TooManyAccounts);
+            }
+        }
+
+        let hash = self.hashing.hash(password)?;
 
         let _: Option<Record> = self
-            .db
-            .create("credentials")
-            .content(Credentials {
-                name: name.to_string(),
-                hash,
+            .retry
+            .run(|| async {
+                self.db
+                    .create("credentials")
+                    .content(Credentials {
+                        name: name.to_string(),
+                        hash: hash.clone(),
+                        totp: None,
+                        email: email.map(str::to_string),
+                        ip: ip.map(str::to_string),
+                    })
+                    .await
             })
             .await?;
 
-        Ok(true)
+        Ok(Registration::Created)
+    }
+
+    async fn accounts_for_ip(&self, ip: &str) -> anyhow::Result<usize> {
+        let count: Option<usize> = self
+            .retry
+            .run(|| async {
+                self.db
+                    .query("SELECT count() FROM credentials WHERE ip = $ip GROUP ALL")
+                    .bind(("ip", ip.to_string()))
+                    .await?
+                    .take("count")
+            })
+            .await?;
+
+        Ok(count.unwrap_or(0))
     }
 
     pub async fn authenticate(&self, name: &str, password: &str) -> anyhow::Result<bool> {
-        if !self.player_exists(&name).await? {
+        let Some(user) = self.find_credentials(name).await? else {
             return Ok(false);
+        };
+
+        let verification = self.hashing.verify(password, &user.hash)?;
+        if verification.matches && verification.needs_rehash {
+            self.rehash(name, password).await?;
         }
+        Ok(verification.matches)
+    }
 
-        let argon2 = Argon2::default();
+    async fn rehash(&self, name: &str, password: &str) -> anyhow::Result<()> {
+        self.set_password(name, password).await?;
 
-        let users: Vec<Credentials> = self.db.select("credentials").await?;
-        let user = users.iter().find(|a| a.name == name);
+        log::info!("Upgraded the password hash of {} to the current scheme and parameters.", name);
+        Ok(())
+    }
 
-        if let Some(user) = user {
-            let hash = PasswordHash::new(&user.hash)?;
+    /// Replaces the password of `name` if `old_password` is correct.
+    pub async fn change_password(&self, name: &str, old_password: &str, new_password: &str) -> anyhow::Result<bool> {
+        if !self.authenticate(name, old_password).await? {
+            return Ok(false);
+        }
 
-            if argon2.verify_password(password.as_bytes(), &hash).is_ok() {
-                return Ok(true);
-            }
+        self.set_password(name, new_password).await?;
+        Ok(true)
+    }
+
+    /// Deletes the account of `name` and its saved sessions if `password` is correct. Bans
+    /// and whitelist entries stay.
+    pub async fn delete_account(&self, name: &str, password: &str) -> anyhow::Result<bool> {
+        if !self.authenticate(name, password).await? {
+            return Ok(false);
+        }
+
+        self.retry
+            .run(|| async {
+                self.db
+                    .query("DELETE credentials WHERE name = $name; DELETE sessions WHERE name = $name")
+                    .bind(("name", name.to_string()))
+                    .await?
+                    .check()
+            })
+            .await?;
+
+        Ok(true)
+    }
+
+    /// Sets a new password without checking the old one. Returns false if there is no such
+    /// account.
+    pub async fn reset_password(&self, name: &str, password: &str) -> anyhow::Result<bool> {
+        if !self.player_exists(name).await? {
+            return Ok(false);
+        }
+
+        self.set_password(name, password).await?;
+        Ok(true)
+    }
+
+    async fn set_password(&self, name: &str, password: &str) -> anyhow::Result<()> {
+        let hash = self.hashing.hash(password)?;
+
+        self.retry
+            .run(|| async {
+                self.db
+                    .query("UPDATE credentials SET hash = $hash WHERE name = $name")
+                    .bind(("hash", hash.clone()))
+                    .bind(("name", name.to_string()))
+                    .await?
+                    .check()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn find_credentials(&self, name: &str) -> anyhow::Result<Option<Credentials>> {
+        let users: Vec<Credentials> = self.retry.run(|| async { self.db.select("credentials").await }).await?;
+        Ok(users.into_iter().find(|a| a.name == name))
+    }
+
+    fn totp_key(&self) -> anyhow::Result<&[u8; 32]> {
+        self.totp_key
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Two-factor authentication is not configured"))
+    }
+
+    pub async fn has_totp(&self, name: &str) -> anyhow::Result<bool> {
+        Ok(self
+            .find_credentials(name)
+            .await?
+            .is_some_and(|user| user.totp.is_some()))
+    }
+
+    pub async fn enable_totp(&self, name: &str, secret: &[u8]) -> anyhow::Result<()> {
+        let encrypted = totp::encrypt_secret(self.totp_key()?, secret)?;
+
+        self.retry
+            .run(|| async {
+                self.db
+                    .query("UPDATE credentials SET totp = $totp WHERE name = $name")
+                    .bind(("totp", encrypted.clone()))
+                    .bind(("name", name.to_string()))
+                    .await?
+                    .check()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn verify_totp(&self, name: &str, code: &str) -> anyhow::Result<bool> {
+        let Some(encrypted) = self.find_credentials(name).await?.and_then(|user| user.totp) else {
+            return Ok(false);
+        };
+
+        let secret = totp::decrypt_secret(self.totp_key()?, &encrypted)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        Ok(totp::verify(&secret, code, now))
+    }
+
+    pub async fn create_session(&self, name: &str, ip: &str, ttl: Duration) -> anyhow::Result<()> {
+        let expires = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + ttl.as_secs();
+
+        self.retry
+            .run(|| async {
+                self.db
+                    .query("DELETE sessions WHERE name = $name AND ip = $ip")
+                    .bind(("name", name.to_string()))
+                    .bind(("ip", ip.to_string()))
+                    .await?
+                    .check()
+            })
+            .await?;
+
+        let _: Option<Record> = self
+            .retry
+            .run(|| async {
+                self.db
+                    .create("sessions")
+                    .content(Session {
+                        name: name.to_string(),
+                        ip: ip.to_string(),
+                        expires,
+                    })
+                    .await
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn has_session(&self, name: &str, ip: &str) -> anyhow::Result<bool> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        let sessions: Vec<Session> = self
+            .retry
+            .run(|| async {
+                self.db
+                    .query("SELECT * FROM sessions WHERE name = $name AND ip = $ip AND expires > $now")
+                    .bind(("name", name.to_string()))
+                    .bind(("ip", ip.to_string()))
+                    .bind(("now", now))
+                    .await?
+                    .take(0)
+            })
+            .await?;
+
+        Ok(!sessions.is_empty())
+    }
+
+    pub async fn clear_sessions(&self, name: &str) -> anyhow::Result<()> {
+        self.retry
+            .run(|| async {
+                self.db
+                    .query("DELETE sessions WHERE name = $name")
+                    .bind(("name", name.to_string()))
+                    .await?
+                    .check()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn ban(&self, name: &str) -> anyhow::Result<()> {
+        if self.is_banned(name).await? {
+            return Ok(());
+        }
+
+        let _: Option<Record> = self
+            .retry
+            .run(|| async {
+                self.db
+                    .create("bans")
+                    .content(Ban {
+                        name: name.to_string(),
+                    })
+                    .await
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn is_banned(&self, name: &str) -> anyhow::Result<bool> {
+        let bans: Vec<Ban> = self.retry.run(|| async { self.db.select("bans").await }).await?;
+        Ok(bans.iter().any(|ban| ban.name == name))
+    }
+
+    pub async fn whitelist_add(&self, name: &str) -> anyhow::Result<()> {
+        if self.is_whitelisted(name).await? {
+            return Ok(());
         }
 
-        Ok(false)
+        let _: Option<Record> = self
+            .retry
+            .run(|| async {
+                self.db
+                    .create("whitelist")
+                    .content(WhitelistEntry {
+                        name: name.to_string(),
+                    })
+                    .await
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn whitelist_remove(&self, name: &str) -> anyhow::Result<()> {
+        self.retry
+            .run(|| async {
+                self.db
+                    .query("DELETE whitelist WHERE name = $name")
+                    .bind(("name", name.to_string()))
+                    .await?
+                    .check()
+            })
+            .await?;
+
+        Ok(())
     }
-}
\ No newline at end of file
+
+    pub async fn is_whitelisted(&self, name: &str) -> anyhow::Result<bool> {
+        let entries: Vec<WhitelistEntry> = self
+            .retry
+            .run(|| async {
+                self.db
+                    .query("SELECT * FROM whitelist WHERE name = $name")
+                    .bind(("name", name.to_string()))
+                    .await?
+                    .take(0)
+            })
+            .await?;
+
+        Ok(!entries.is_empty())
+    }
+}
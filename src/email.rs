@@ -0,0 +1,50 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+/// Longest address SMTP can carry.
+const MAX_EMAIL_LENGTH: usize = 254;
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+
+/// A deliberately loose check that `email` looks like `local@example.com`. Whether it
+/// actually exists is up to the confirmation mail.
+pub fn valid_email(email: &str) -> bool {
+    if email.len() > MAX_EMAIL_LENGTH {
+        return false;
+    }
+
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+
+    let local_valid = !local.is_empty()
+        && local.len() <= MAX_LOCAL_PART_LENGTH
+        && local.chars().all(|c| c.is_ascii_graphic() && !"@\"(),:;<>[\\]".contains(c));
+
+    let labels = domain.split('.').collect::<Vec<_>>();
+    let domain_valid = labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+
+    local_valid && domain_valid
+}
+
+/// Tells players about their account by email, e.g. to confirm the address they
+/// registered with.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send_confirmation(&self, name: &str, email: &str) -> Result<()>;
+}
+
+/// Sends nothing. Used until a real notifier is set up.
+pub struct NoopNotifier;
+
+#[async_trait]
+impl Notifier for NoopNotifier {
+    async fn send_confirmation(&self, _name: &str, _email: &str) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+
+/// Hooks for embedding the limbo, e.g. to post joins to Discord or track logins elsewhere.
+/// Every method does nothing unless overridden. They run on the connection's task, so
+/// slow work belongs in a spawned task.
+#[async_trait]
+pub trait EventHandler: Send + Sync {
+    /// A connection was accepted, before anything was read from it.
+    async fn on_connect(&self, _peer: SocketAddr) {}
+
+    /// A player proved who they are, by password, 2FA code or saved session.
+    async fn on_login(&self, _name: &str, _peer: SocketAddr) {}
+
+    async fn on_register(&self, _name: &str, _peer: SocketAddr) {}
+
+    /// A player who had joined the limbo left. Status pings and connections dropped
+    /// during login don't get here.
+    async fn on_disconnect(&self, _name: &str) {}
+}
+
+/// Ignores every event. Used unless something else is set up.
+pub struct NoopEventHandler;
+
+impl EventHandler for NoopEventHandler {}
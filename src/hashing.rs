@@ -0,0 +1,189 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+
+use crate::config::{Argon2Config, HashAlgorithm, HashingConfig};
+
+/// A password hashing scheme.
+pub trait Hasher: Send + Sync {
+    /// Whether `hash` was produced by this scheme, judged by its prefix.
+    fn recognizes(&self, hash: &str) -> bool;
+
+    fn hash(&self, password: &str) -> Result<String>;
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool>;
+
+    /// Whether `hash`, which this scheme recognizes, was created with other parameters than
+    /// the current ones.
+    fn needs_rehash(&self, hash: &str) -> bool;
+}
+
+/// Environment variable the Argon2 pepper is read from when `argon2.pepper_file` isn't set.
+const PEPPER_ENV: &str = "VOID_ARGON2_PEPPER";
+
+pub struct Argon2Hasher {
+    params: Params,
+    /// Secret mixed into every hash, kept out of the database.
+    pepper: Option<Vec<u8>>,
+}
+
+impl Argon2Hasher {
+    pub fn new(config: &Argon2Config) -> Result<Self> {
+        let params = Params::new(config.memory_kib, config.iterations, config.parallelism, None)
+            .map_err(|e| anyhow!("Invalid Argon2 parameters: {e}"))?;
+        Self::with_pepper(params, load_pepper(config)?)
+    }
+
+    fn with_pepper(params: Params, pepper: Option<Vec<u8>>) -> Result<Self> {
+        let hasher = Self { params, pepper };
+        hasher.argon2()?;
+        Ok(hasher)
+    }
+
+    fn argon2(&self) -> Result<Argon2<'_>> {
+        match &self.pepper {
+            Some(pepper) => Argon2::new_with_secret(pepper, Algorithm::Argon2id, Version::V0x13, self.params.clone())
+                .map_err(|e| anyhow!("Invalid Argon2 pepper: {e}")),
+            None => Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())),
+        }
+    }
+}
+
+/// Reads the pepper from `argon2.pepper_file`, or the environment. Trailing whitespace, like
+/// the newline most editors leave, isn't part of it.
+fn load_pepper(config: &Argon2Config) -> Result<Option<Vec<u8>>> {
+    let pepper = match &config.pepper_file {
+        Some(path) => std::fs::read(path).map_err(|e| anyhow!("Failed to read the Argon2 pepper from {path}: {e}"))?,
+        None => match std::env::var_os(PEPPER_ENV) {
+            Some(value) => value.into_encoded_bytes(),
+            None => return Ok(None),
+        },
+    };
+
+    let pepper = pepper.trim_ascii_end().to_vec();
+    if pepper.is_empty() {
+        return Err(anyhow!("The Argon2 pepper is empty"));
+    }
+    Ok(Some(pepper))
+}
+
+impl Hasher for Argon2Hasher {
+    fn recognizes(&self, hash: &str) -> bool {
+        hash.starts_with("$argon2")
+    }
+
+    fn hash(&self, password: &str) -> Result<String> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = self.argon2()?.hash_password(password.as_bytes(), &salt)?;
+        Ok(hash.serialize().to_string())
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool> {
+        let hash = PasswordHash::new(hash)?;
+        Ok(self.argon2()?.verify_password(password.as_bytes(), &hash).is_ok())
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        let Ok(hash) = PasswordHash::new(hash) else {
+            return true;
+        };
+        let Ok(params) = Params::try_from(&hash) else {
+            return true;
+        };
+        let current = &self.params;
+
+        hash.algorithm != Algorithm::Argon2id.ident()
+            || hash.version != Some(Version::V0x13.into())
+            || params.m_cost() != current.m_cost()
+            || params.t_cost() != current.t_cost()
+            || params.p_cost() != current.p_cost()
+    }
+}
+
+pub struct BcryptHasher {
+    cost: u32,
+}
+
+impl BcryptHasher {
+    pub fn new(cost: u32) -> Result<Self> {
+        // The range the bcrypt crate accepts
+        if !(4..=31).contains(&cost) {
+            return Err(anyhow!("Invalid bcrypt cost {cost}"));
+        }
+        Ok(Self { cost })
+    }
+}
+
+impl Hasher for BcryptHasher {
+    fn recognizes(&self, hash: &str) -> bool {
+        ["$2a$", "$2b$", "$2x$", "$2y$"].iter().any(|prefix| hash.starts_with(prefix))
+    }
+
+    fn hash(&self, password: &str) -> Result<String> {
+        Ok(bcrypt::hash(password, self.cost)?)
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool> {
+        Ok(bcrypt::verify(password, hash)?)
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        // $2b$12$<salt and hash>
+        hash.get(4..6).and_then(|cost| cost.parse::<u32>().ok()) != Some(self.cost)
+    }
+}
+
+/// New passwords are hashed with the configured scheme, while stored hashes are checked
+/// with whichever scheme produced them.
+pub struct PasswordHashing {
+    preferred: Box<dyn Hasher>,
+    others: Vec<Box<dyn Hasher>>,
+}
+
+/// Result of checking a password against a stored hash.
+pub struct Verification {
+    pub matches: bool,
+    /// The hash should be replaced with one from the preferred scheme and parameters.
+    pub needs_rehash: bool,
+}
+
+impl PasswordHashing {
+    pub fn new(config: &HashingConfig, argon2: &Argon2Config) -> Result<Self> {
+        let argon2: Box<dyn Hasher> = Box::new(Argon2Hasher::new(argon2)?);
+        let bcrypt: Box<dyn Hasher> = Box::new(BcryptHasher::new(config.bcrypt_cost)?);
+
+        let (preferred, other) = match config.algorithm {
+            HashAlgorithm::Argon2 => (argon2, bcrypt),
+            HashAlgorithm::Bcrypt => (bcrypt, argon2),
+        };
+        Ok(Self {
+            preferred,
+            others: vec![other],
+        })
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String> {
+        self.preferred.hash(password)
+    }
+
+    pub fn verify(&self, password: &str, hash: &str) -> Result<Verification> {
+        if self.preferred.recognizes(hash) {
+            return Ok(Verification {
+                matches: self.preferred.verify(password, hash)?,
+                needs_rehash: self.preferred.needs_rehash(hash),
+            });
+        }
+
+        let hasher = self
+            .others
+            .iter()
+            .find(|hasher| hasher.recognizes(hash))
+            .ok_or_else(|| anyhow!("Unrecognized password hash scheme"))?;
+        Ok(Verification {
+            matches: hasher.verify(password, hash)?,
+            needs_rehash: true,
+        })
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+
+use crate::metrics::{self, Metrics};
+use crate::Context;
+
+/// A check that takes longer than this fails, so a wedged instance is reported as down
+/// rather than leaving the probe hanging.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub async fn serve(bind: String, context: Arc<Mutex<Context>>, metrics: Arc<Metrics>) -> Result<()> {
+    let listener = TcpListener::bind(&bind).await?;
+    log::info!("Serving health checks on {}", bind);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let context = Arc::clone(&context);
+        let metrics = Arc::clone(&metrics);
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_request(stream, &context, &metrics).await {
+                log::debug!("Health check from {} failed: {:?}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_request(mut stream: TcpStream, context: &Mutex<Context>, metrics: &Metrics) -> Result<()> {
+    let Some((method, path)) = metrics::read_request(&mut stream).await? else {
+        return Ok(());
+    };
+
+    let (status, body) = match (method.as_str(), path.as_str()) {
+        ("GET", "/health") => {
+            let db_up = db_up(context).await;
+            let body = json::object! {
+                status: if db_up { "ok" } else { "unavailable" },
+                connections: metrics.active_connections.get(),
+                db: if db_up { "up" } else { "down" },
+            };
+            let status = if db_up { "200 OK" } else { "503 Service Unavailable" };
+            (status, json::stringify(body))
+        }
+        _ => ("404 Not Found", String::new()),
+    };
+    metrics::write_response(&mut stream, status, "application/json", &body).await
+}
+
+async fn db_up(context: &Mutex<Context>) -> bool {
+    let db = Arc::clone(&context.lock().await.db);
+    let check = tokio::time::timeout(CHECK_TIMEOUT, db.check_db()).await;
+    match check {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            log::warn!("Health check: database error: {:?}", e);
+            false
+        }
+        Err(_) => {
+            log::warn!("Health check: no answer within {:?}", CHECK_TIMEOUT);
+            false
+        }
+    }
+}
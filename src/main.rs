@@ -1,98 +1,349 @@
-use std::io::Read;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{io::Cursor, net::SocketAddr, sync::Arc};
 use anyhow::anyhow;
 use anyhow::Result;
-use nbt::{NamedTag, NBT};
-use protocol::{packet::PacketBuilder, varint::VarInt};
-use surrealdb::Surreal;
+use backend::BackendSelector;
+use capture::{Capture, Direction};
+use commands::{CommandOutcome, CommandRegistry};
+use config::{Config, SharedConfig, VoidAction};
+use db::{Database, Registration};
+use email::{NoopNotifier, Notifier};
+use events::{EventHandler, NoopEventHandler};
+use messages::Messages;
+use metrics::Metrics;
+use passthrough::StatusCache;
+use players::{ConnectionHandle, Outbound, PlayerRegistry};
+use protocol::login::LoginStart;
+use protocol::play::PlayIds;
+use protocol::settings::ClientSettings;
+use protocol::{bungee, packet::PacketBuilder, plugin, varint::VarInt, ProtocolError};
+use queue::{ConnectionQueue, Slot, Ticket};
+use ratelimit::{ConnectionLimiter, PacketLimiter, RegistrationCooldown};
 use tokio::{
-    io::AsyncWriteExt,
+    io::{AsyncReadExt, AsyncWriteExt, BufWriter},
     net::{TcpListener, TcpStream},
+    sync::mpsc::{self, UnboundedReceiver},
     sync::Mutex,
 };
-use tokio_byteorder::{AsyncReadBytesExt, BigEndian};
-
+use uuid::Uuid;
+use world::World;
+
+pub mod backend;
+pub mod capture;
+pub mod captcha;
+pub mod chat;
+pub mod codec;
+pub mod commands;
+pub mod config;
 pub mod db;
+pub mod email;
+pub mod events;
+pub mod hashing;
+pub mod health;
+pub mod messages;
+pub mod metrics;
 pub mod nbt;
+pub mod passthrough;
+pub mod players;
 pub mod protocol;
+pub mod queue;
+pub mod ratelimit;
+pub mod rcon;
+pub mod totp;
+pub mod world;
+
+/// Read at startup and again by `/reload`.
+const CONFIG_PATH: &str = "config.toml";
 
 pub struct Context {
-    db: Surreal<surrealdb::engine::local::Db>,
+    config: Arc<SharedConfig>,
+    db: Arc<Database>,
+    backends: BackendSelector,
+    players: PlayerRegistry,
+    /// Connection slots, and the connections waiting for one once `rate_limit.max_connections`
+    /// is reached.
+    queue: Arc<ConnectionQueue>,
+    metrics: Arc<Metrics>,
+    status_cache: Arc<StatusCache>,
+    commands: CommandRegistry,
+    /// Recent connections per IP, checked once the PROXY header has given the real address.
+    connections: ConnectionLimiter,
+    registrations: RegistrationCooldown,
+    notifier: Arc<dyn Notifier>,
+    events: Arc<dyn EventHandler>,
 }
 
+impl Context {
+    /// Opens the database and sets up everything else the config asks for.
+    pub async fn new(shared_config: Arc<SharedConfig>, metrics: Arc<Metrics>) -> Result<Self> {
+        let config = shared_config.get();
+        Ok(Context {
+            config: shared_config,
+            db: Arc::new(Database::new(&config).await?),
+            backends: BackendSelector::default(),
+            players: PlayerRegistry::default(),
+            queue: Arc::new(ConnectionQueue::new(config.rate_limit.max_connections, config.rate_limit.queue_size)),
+            metrics,
+            status_cache: Arc::default(),
+            commands: CommandRegistry::with_defaults(),
+            connections: ConnectionLimiter::new(
+                config.rate_limit.connections,
+                Duration::from_secs(config.rate_limit.window_secs),
+            ),
+            registrations: RegistrationCooldown::new(Duration::from_secs(config.rate_limit.registration_cooldown_secs)),
+            notifier: Arc::new(NoopNotifier),
+            events: Arc::new(NoopEventHandler),
+        })
+    }
+
+    /// Re-reads the config for new connections and applies the settings kept in here.
+    pub fn reload_config(&mut self) -> Result<()> {
+        let config = self.config.reload()?;
+        self.connections
+            .set_limit(config.rate_limit.connections, Duration::from_secs(config.rate_limit.window_secs));
+        self.registrations
+            .set_cooldown(Duration::from_secs(config.rate_limit.registration_cooldown_secs));
+        Ok(())
+    }
+}
+
+/// A client connection. Packets are buffered and flushed together once the connection has
+/// handled whatever woke it up, before it waits for the client again.
+pub type ClientStream = BufWriter<TcpStream>;
+
 pub struct State {
     state: i32,
+    protocol_version: i32,
+    registry_codec: Option<&'static codec::CodecEntry>,
+    /// Play packet IDs for the client's protocol version, chosen along with the codec.
+    ids: &'static PlayIds,
     peer: SocketAddr,
     real_address: String,
     username: String,
+    uuid: Uuid,
+    authenticated: bool,
+    /// Set when the handshake carried a Forge (FML) marker.
+    modded: bool,
+    /// Whether the player has moved away from spawn or swung their arm.
+    has_moved: bool,
+    /// Set when the player reports a position below spawn, cleared by the next teleport.
+    falling: bool,
+    /// Locale, view distance and the like, as last reported by the client.
+    settings: ClientSettings,
+    /// Whether the player already had an account when the login prompt was shown.
+    registered: Option<bool>,
+    /// Code on the captcha map, until the player enters it with /verify.
+    captcha: Option<String>,
+    /// Set when `capture_dir` is configured.
+    capture: Option<Capture>,
+    packet_limiter: PacketLimiter,
+    connected_at: Instant,
+    /// When the player spawned in the limbo.
+    joined_at: Option<Instant>,
+    /// How many of the configured welcome lines have been sent.
+    welcome_sent: usize,
+    /// When the next welcome line is due, while there are more to send.
+    next_welcome_at: Option<Instant>,
+    /// Set once the player has been logged as slow to log in, so it's only logged once.
+    slow_login: bool,
+    login_deadline: Option<Instant>,
+    /// When a player with a saved session is logged in without a prompt.
+    session_login_at: Option<Instant>,
+    awaiting_totp: bool,
+    pending_totp: Option<Vec<u8>>,
+    /// The connection slot, once the connection has one. Dropping it lets in the next
+    /// connection in line.
+    slot: Option<Slot>,
+    /// Place in line while waiting for a slot. The login prompt is held back until then.
+    queued: Option<Ticket>,
     context: Arc<Mutex<Context>>,
+    config: Arc<Config>,
     conn_id: i32,
+    /// Handle other tasks use to reach this connection, e.g. RCON broadcasts.
+    handle: ConnectionHandle,
+    outbound: UnboundedReceiver<Outbound>,
 }
 
 impl State {
-    pub fn new(context: Arc<Mutex<Context>>, peer: SocketAddr) -> Self {
+    pub fn new(context: Arc<Mutex<Context>>, config: Arc<Config>, peer: SocketAddr) -> Self {
+        let (handle, outbound) = ConnectionHandle::new();
+        let packet_limiter = PacketLimiter::new(
+            config.rate_limit.packets_per_sec,
+            config.rate_limit.packet_burst,
+            Instant::now(),
+        );
+
         State {
             state: 0,
+            protocol_version: 0,
+            registry_codec: None,
+            ids: &PlayIds::V1_19_2,
             peer,
             username: String::from("<name unknown>"),
+            uuid: Uuid::nil(),
+            authenticated: false,
+            modded: false,
+            has_moved: false,
+            falling: false,
+            settings: ClientSettings::default(),
+            registered: None,
+            captcha: None,
+            capture: None,
+            packet_limiter,
+            connected_at: Instant::now(),
+            joined_at: None,
+            welcome_sent: 0,
+            next_welcome_at: None,
+            slow_login: false,
+            login_deadline: None,
+            session_login_at: None,
+            awaiting_totp: false,
+            pending_totp: None,
+            slot: None,
+            queued: None,
             real_address: String::from("<IP address unknown>"),
             context,
+            config,
             conn_id: rand::random(),
+            handle,
+            outbound,
         }
     }
 
+    async fn players(&self) -> PlayerRegistry {
+        Arc::clone(&self.context.lock().await.players)
+    }
+
+    fn is_admin(&self) -> bool {
+        self.config.admins.contains(&self.username)
+    }
+
+    /// Messages in the player's language, or the default one until the client reports it.
+    fn messages(&self) -> &Messages {
+        self.config.messages.get(&self.settings.locale)
+    }
+
+    async fn db(&self) -> Arc<Database> {
+        Arc::clone(&self.context.lock().await.db)
+    }
+
+    async fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.context.lock().await.metrics)
+    }
+
+    async fn events(&self) -> Arc<dyn EventHandler> {
+        Arc::clone(&self.context.lock().await.events)
+    }
+
     pub async fn send_packet(
         &self,
-        stream: &mut TcpStream,
+        stream: &mut ClientStream,
         packet: impl Into<Vec<u8>>,
     ) -> anyhow::Result<()> {
-        stream.write_all(&packet.into()).await?;
-        stream.flush().await?;
-        Ok(())
+        let packet = packet.into();
+        if let Some(capture) = &self.capture {
+            if let Err(e) = capture.record_clientbound(&packet).await {
+                log::warn!("Failed to capture a packet to {}: {:?}", self.peer, e);
+            }
+        }
+
+        protocol::write_packet(stream, packet).await
     }
 
-    pub async fn receive_packet(&mut self, stream: &mut TcpStream) -> Result<()> {
-        let Ok((packet_id, buffer)) = protocol::read_generic_packet(stream).await else {
-            self.state = -1;
-            return Ok(());
-        };
+    pub async fn receive_packet(&mut self, stream: &mut ClientStream) -> Result<()> {
+        let (packet_id, buffer) = protocol::read_generic_packet(stream).await?;
+        if let Some(capture) = &self.capture {
+            if let Err(e) = capture.record(Direction::Serverbound, packet_id, &buffer).await {
+                log::warn!("Failed to capture a packet from {}: {:?}", self.peer, e);
+            }
+        }
+
+        // Keep alives are answers to the server, so they never count against the limit
+        let keep_alive = self.state == 3 && packet_id == self.ids.serverbound_keep_alive;
+        if !keep_alive && !self.packet_limiter.allow(Instant::now()) {
+            log::warn!("{} [{}] sent too many packets.", self.username, self.real_address);
+            return self.kick(stream, &self.messages().packet_spam, None).await;
+        }
+
         let mut buffer = Cursor::new(buffer);
 
         match self.state {
-            0 => match packet_id {
-                0 => {
-                    let _protocol_version = VarInt::read(&mut buffer).await?.into_inner();
-                    let _server_address = protocol::read_string(&mut buffer).await?;
-                    let _server_port = buffer.read_u16::<BigEndian>().await?;
-                    let next_state = VarInt::read(&mut buffer).await?.into_inner();
+            0 => {
+                if packet_id != 0 {
+                    return Err(ProtocolError::UnknownPacket { state: 0, id: packet_id }.into());
+                }
+
+                let protocol_version = VarInt::read(&mut buffer).await?.into_inner();
+                let server_address = protocol::read_string(&mut buffer).await?;
+                let _server_port = buffer.read_u16().await?;
+                let next_state = VarInt::read(&mut buffer).await?.into_inner();
 
-                    self.state = next_state;
+                // Status and login are the only states a handshake can lead to here
+                if !matches!(next_state, 1 | 2) {
+                    return Err(anyhow!("{} sent a handshake for unknown state {}", self.peer, next_state));
                 }
-                _ => ()
-            },
+
+                self.protocol_version = protocol_version;
+                self.modded = protocol::is_fml_address(&server_address);
+                self.state = next_state;
+
+                // Fine for a ping, but there is no version to log in with
+                if protocol_version == protocol::STATUS_ONLY_PROTOCOL && next_state == 2 {
+                    log::warn!("{} tried to log in with the status-only protocol version.", self.peer);
+                    return self.reject_version(stream).await;
+                }
+            }
             1 => match packet_id {
                 0 => {
-                    let payload = include_str!("status_response.json");
+                    let payload = self.status_response().await?;
 
-                    let response = PacketBuilder::new(0x00).with_string(payload).build();
+                    let response = PacketBuilder::new(0x00).with_string(&payload).build();
 
                     self.send_packet(stream, response).await?;
                 }
+                // Answered whether or not a status request came first. Like vanilla, the
+                // connection is closed after the pong, since nothing else can follow it
                 1 => {
-                    let payload = buffer.read_i64::<BigEndian>().await?;
+                    let payload = buffer.read_i64().await?;
 
-                    stream
-                        .write_all(&PacketBuilder::new(0x01).with_i64(payload).build())
+                    self.send_packet(stream, PacketBuilder::new(0x01).with_i64(payload))
                         .await?;
-                    stream.flush().await?;
+                    self.state = -1;
                 }
-                _ => ()
+                id => return self.unknown_packet(stream, id).await,
             },
             2 => match packet_id {
                 0 => {
-                    let username = protocol::read_string(&mut buffer).await?;
+                    let LoginStart { username, uuid } = LoginStart::read(&mut buffer, self.protocol_version).await?;
+                    if !players::valid_username(&username) {
+                        log::warn!("{} sent an invalid username {:?}.", self.peer, username);
+                        return self.kick(stream, &self.messages().invalid_username, None).await;
+                    }
 
                     self.username = username.clone();
+                    // Replaced by the forwarded UUID once the proxy answers
+                    if let Some(uuid) = uuid {
+                        self.uuid = uuid;
+                    }
+
+                    let Some(codec) = codec::negotiate(self.protocol_version, &self.config.versions) else {
+                        log::warn!(
+                            "{} [{}] connected with unsupported protocol version {}.",
+                            self.username,
+                            self.peer,
+                            self.protocol_version
+                        );
+                        return self.reject_version(stream).await;
+                    };
+                    self.registry_codec = Some(codec);
+                    self.ids = PlayIds::for_protocol(self.protocol_version)
+                        .ok_or_else(|| anyhow!("No play packet IDs for protocol {}", self.protocol_version))?;
+
+                    if self.modded && !self.config.forge.allow {
+                        log::info!("{} [{}] connected with a modded client.", self.username, self.peer);
+                        let message = self.config.forge.kick_message.clone();
+                        return self.kick(stream, message, None).await;
+                    }
 
                     let response = PacketBuilder::new(0x04)
                         .with_var_int(self.conn_id.abs())
@@ -103,26 +354,38 @@ impl State {
                     self.send_packet(stream, response).await?;
                 }
                 0x02 => {
-                    let message_id = VarInt::read(&mut buffer).await?;
+                    let message_id = VarInt::read(&mut buffer).await?.into_inner();
+
+                    // Forge clients may answer channels we never asked about; acknowledge
+                    // them by ignoring anything that isn't the Velocity forwarding reply
+                    if message_id != self.conn_id.abs() {
+                        log::debug!("Ignoring login plugin response {} from {}", message_id, self.peer);
+                        return Ok(());
+                    }
 
                     match buffer.read_u8().await? {
                         1 => {
                             let mut signature = vec![0u8; 32];
-                            buffer.read_exact(&mut signature)?;
+                            buffer.read_exact(&mut signature).await?;
 
                             let version = VarInt::read(&mut buffer).await?;
                             let address = protocol::read_string(&mut buffer).await?;
-                            let uuid = buffer.read_u128::<BigEndian>().await?;
+                            let uuid = buffer.read_u128().await?;
                             self.real_address = address;
+                            self.uuid = Uuid::from_u128(uuid);
 
                             let username = protocol::read_string(&mut buffer).await?;
+                            if !players::valid_username(&username) {
+                                log::warn!("{} forwarded an invalid username {:?}.", self.peer, username);
+                                return self.kick(stream, &self.messages().invalid_username, None).await;
+                            }
                             self.username = username;
                             
                             let properties_len = VarInt::read(&mut buffer).await?;
 
                             for _ in 0..properties_len.into_inner() {
-                                let name = protocol::read_string(&mut buffer).await?;
-                                let value = protocol::read_string(&mut buffer).await?;
+                                let _name = protocol::read_string(&mut buffer).await?;
+                                let _value = protocol::read_string(&mut buffer).await?;
                                 let has_signature = buffer.read_u8().await?;
                                 if has_signature == 1 {
                                     let _signature = protocol::read_string(&mut buffer).await?;
@@ -131,7 +394,7 @@ impl State {
 
                             if version.into_inner() == 2 {
                                 let mut _ignored = vec![0u8; 8 + 512 + 4096];
-                                buffer.read_exact(&mut signature)?;
+                                buffer.read_exact(&mut signature).await?;
                             }
                         }
                         _ => {
@@ -139,355 +402,887 @@ impl State {
                         }
                     }
 
+                    let banned = self.db().await.is_banned(&self.username).await;
+                    match banned {
+                        Ok(true) => {
+                            log::warn!("{} [{}] is banned.", self.username, self.real_address);
+                            return self.kick(stream, &self.messages().banned, None).await;
+                        }
+                        Ok(false) => {}
+                        Err(e) => return self.kick_db_error(stream, e).await,
+                    }
+
+                    if self.config.whitelist.enabled && !self.is_admin() {
+                        let whitelisted = self.db().await.is_whitelisted(&self.username).await;
+                        match whitelisted {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                log::info!("{} [{}] is not whitelisted.", self.username, self.real_address);
+                                let message = self.config.whitelist.kick_message.clone();
+                                return self.kick(stream, message, None).await;
+                            }
+                            Err(e) => return self.kick_db_error(stream, e).await,
+                        }
+                    }
+
                     // Proceed with normal login sequence
 
                     // Send login success
 
                     let response = PacketBuilder::new(0x02)
-                        .with_uuid(0)
+                        .with_uuid_value(self.uuid)
                         .with_string(&self.username)
-                        .with_var_int(0)
+                        .with_var_int(0) // properties
                         .build();
 
                     self.send_packet(stream, response).await?;
 
-                    let registry_codec = nbt::from_json(include_str!("registry_codec.json"));
-
-                    let response = PacketBuilder::new(0x25)
-                        .with_i32(0) // entity id
-                        .with_bool(false) // is hardcore
-                        .with_u8(3) // gamemode
-                        .with_u8(0xff) // previous gamemode
-                        .with_var_int(1) // dim count
-                        .with_string("minecraft:the_end") // dim name
-                        .with_nbt(&registry_codec)
-                        .with_string("minecraft:the_end") // dimension type
-                        .with_string("minecraft:the_end") // dimension name
-                        .with_i64(0) // hashed (and truncated) seed
-                        .with_var_int(20) // max players
-                        .with_var_int(2) // view distance
-                        .with_var_int(2) // simulation distance
-                        .with_bool(false) // reduce debug info
-                        .with_bool(false) // enable respawn screen
-                        .with_bool(true) // is debug
-                        .with_bool(false) // is flat
-                        .with_bool(false) // has death location
-                        .build();
+                    // The client switches to the "play" state as soon as it receives login success
+                    self.enter_play(stream).await?;
+                }
+                id => return self.unknown_packet(stream, id).await,
+            },
+            3 => {
+                let ids = self.ids;
+                match packet_id {
+                    id if id == ids.serverbound_keep_alive => {
+                        let payload = buffer.read_i64().await?;
 
-                    self.send_packet(stream, response).await?;
+                        self.send_packet(stream, PacketBuilder::new(ids.keep_alive).with_i64(payload))
+                            .await?;
+                    }
+                    id if id == ids.set_player_position || id == ids.set_player_position_and_rotation => {
+                        let x = buffer.read_f64().await?;
+                        let y = buffer.read_f64().await?;
+                        let z = buffer.read_f64().await?;
+
+                        // Clients also report their position when standing still, so only a
+                        // position away from spawn counts as moving
+                        if (x, y, z) != (0.0, 0.0, 0.0) {
+                            self.has_moved = true;
+                        }
+                        self.falling = y < SPAWN_Y;
+
+                        if y < self.config.void.threshold {
+                            match self.config.void.action {
+                                VoidAction::Teleport => self.teleport_to_spawn(stream).await?,
+                                VoidAction::Kick => {
+                                    log::warn!("{} [{}] fell below Y={}.", self.username, self.real_address, self.config.void.threshold);
+                                    return self.kick(stream, self.config.void.kick_message.clone(), None).await;
+                                }
+                            }
+                        }
+                    }
+                    id if id == ids.swing_arm => {
+                        self.has_moved = true;
+                    }
+                    id if id == ids.client_information => {
+                        let previous = std::mem::replace(&mut self.settings, ClientSettings::read(&mut buffer).await?);
+                        log::debug!("{} [{}] sent client settings: {:?}", self.username, self.real_address, self.settings);
+
+                        // The prompt usually goes out before the client reports its locale
+                        let changed = !std::ptr::eq(self.config.messages.get(&previous.locale), self.messages());
+                        if let (Some(registered), false, true) = (self.registered, self.authenticated, changed) {
+                            self.send_login_prompt(stream, registered).await?;
+                        }
+                    }
+                    id if id == ids.resource_pack_status => {
+                        let result = VarInt::read(&mut buffer).await?.into_inner();
+                        let forced = self.config.resource_pack.as_ref().is_some_and(|pack| pack.forced);
+
+                        match result {
+                            0 => log::info!("{} [{}] has loaded the resource pack.", self.username, self.real_address),
+                            3 => log::debug!("{} [{}] accepted the resource pack.", self.username, self.real_address),
+                            1 | 2 => {
+                                let outcome = if result == 1 { "declined" } else { "failed to download" };
+                                log::info!("{} [{}] {} the resource pack.", self.username, self.real_address, outcome);
+                                if forced {
+                                    return self.kick(stream, &self.messages().resource_pack_required, None).await;
+                                }
+                            }
+                            _ => log::debug!("Unknown resource pack status {} from {}", result, self.peer),
+                        }
+                    }
+                    id if id == ids.chat_command => {
+                        let command = protocol::read_string(&mut buffer).await?;
+                        return self.run_command(stream, &command).await;
+                    }
+                    id if id == ids.serverbound_plugin_message => {
+                        let channel = protocol::read_string(&mut buffer).await?;
+                        if channel == plugin::BRAND_CHANNEL {
+                            let brand = protocol::read_string(&mut buffer).await?;
+                            log::debug!("{} [{}] is using client brand {:?}.", self.username, self.real_address, brand);
+                        } else if bungee::BungeeMessage::is_channel(&channel) {
+                            let data = &buffer.get_ref()[buffer.position() as usize..];
+                            if let Some(reply) = bungee::answer(data, &self.config.backend.server_name)? {
+                                self.send_packet(stream, reply.into_packet(self.protocol_version, ids)).await?;
+                            }
+                        }
+                    }
+                    id => return self.unknown_packet(stream, id).await,
+                }
+            }
+            _ => {
+                return Err(anyhow!("Unknown connection state."))
+            }
+        }
 
-                    // Send slot select
-                    let response = PacketBuilder::new(0x4a)
-                        .with_u8(0) // slot index
-                        .build();
+        Ok(())
+    }
 
-                    self.send_packet(stream, response).await?;
+    async fn enter_play(&mut self, stream: &mut ClientStream) -> Result<()> {
+        self.state = 3;
+        self.players()
+            .await
+            .lock()
+            .await
+            .insert(self.username.clone(), self.handle.clone());
 
-                    // Send update recipes
-                    let response = PacketBuilder::new(0x6a)
-                        .with_var_int(0) // recipe count
-                        .build();
+        self.join_game(stream).await
+    }
 
-                    self.send_packet(stream, response).await?;
+    /// Sends everything the client needs to spawn in the limbo and welcomes the player, then
+    /// prompts them to log in, or shows them their place in line if they are still waiting
+    /// for a slot.
+    async fn join_game(&mut self, stream: &mut ClientStream) -> Result<()> {
+        let codec = self
+            .registry_codec
+            .ok_or_else(|| anyhow!("No registry codec selected for {}", self.username))?;
+        let registry_codec = nbt::from_json(codec.json)?;
+
+        let response = PacketBuilder::new(self.ids.join_game)
+            .with_i32(0) // entity id
+            .with_bool(false) // is hardcore
+            .with_u8(if self.config.captcha.enabled { 2 } else { 3 }) // gamemode
+            .with_u8(0xff) // previous gamemode
+            .with_prefixed_array(&["minecraft:the_end"], |p, name| p.with_string(name)) // dimension names
+            .with_nbt(&registry_codec)
+            .with_string("minecraft:the_end") // dimension type
+            .with_string("minecraft:the_end") // dimension name
+            .with_i64(0) // hashed (and truncated) seed
+            .with_var_int(20) // max players
+            .with_var_int(self.config.world.view_distance) // view distance
+            .with_var_int(self.config.world.view_distance) // simulation distance
+            .with_bool(false) // reduce debug info
+            .with_bool(false) // enable respawn screen
+            .with_bool(true) // is debug
+            .with_bool(false) // is flat
+            .with_optional(None::<(&str, (i64, i64, i64))>, |p, (dimension, (x, y, z))| {
+                p.with_string(dimension).with_position(x, y, z)
+            }) // death location
+            .build();
 
-                    // Send update tags
-                    let response = PacketBuilder::new(0x6b)
-                        .with_var_int(0) // count
-                        .build();
+        self.send_packet(stream, response).await?;
+        self.send_packet(stream, plugin::brand_packet(self.ids, plugin::SERVER_BRAND)).await?;
 
-                    self.send_packet(stream, response).await?;
+        // Send slot select
+        let response = PacketBuilder::new(self.ids.set_held_item)
+            .with_u8(0) // slot index
+            .build();
 
-                    // Send entity event
-                    let response = PacketBuilder::new(0x1a)
-                        .with_i32(0) // entity id
-                        .with_u8(28) // value
-                        .build();
+        self.send_packet(stream, response).await?;
 
-                    self.send_packet(stream, response).await?;
+        // Send update recipes
+        let response = PacketBuilder::new(self.ids.update_recipes)
+            .with_var_int(0) // recipe count
+            .build();
 
-                    // Send synchronize player position
-                    let response = PacketBuilder::new(0x39)
-                        .with_double(0.0) // x
-                        .with_double(0.0) // y
-                        .with_double(0.0) // z
-                        .with_float(0.0) // yaw
-                        .with_float(0.0) // pitch
-                        .with_u8(0) // flags
-                        .with_var_int(42) // teleport id
-                        .with_bool(false) // dismount vehicle
-                        .build();
+        self.send_packet(stream, response).await?;
 
-                    self.send_packet(stream, response).await?;
+        // Send update tags
+        let response = PacketBuilder::new(self.ids.update_tags)
+            .with_var_int(0) // count
+            .build();
 
-                    // Send empty player info
-                    let response = PacketBuilder::new(0x37)
-                        .with_var_int(0) // action
-                        .with_var_int(0) // player count
-                        .build();
+        self.send_packet(stream, response).await?;
 
-                    self.send_packet(stream, response).await?;
+        // Send entity event
+        let response = PacketBuilder::new(self.ids.entity_event)
+            .with_i32(0) // entity id
+            .with_u8(28) // value
+            .build();
 
-                    // Send set center chunk
-                    let response = PacketBuilder::new(0x4b)
-                        .with_var_int(0) // x
-                        .with_var_int(0) // z
-                        .build();
+        self.send_packet(stream, response).await?;
 
-                    self.send_packet(stream, response).await?;
+        // Send synchronize player position
+        self.teleport_to_spawn(stream).await?;
 
-                    // // Begin sending chunks
+        // Send empty player info
+        let response = PacketBuilder::new(self.ids.player_info)
+            .with_var_int(0) // action
+            .with_prefixed_array(&[] as &[Uuid], |p, uuid| p.with_uuid_value(*uuid)) // players
+            .build();
 
-                    for x in 0..5 {
-                        for z in 0..5 {
-                            let mut data = vec![];
-                            for _ in 0..24 {
-                                data.extend_from_slice(&[
-                                    00u8, 00, 00, 00, 00, 0x01, 0x02, 0x27, 0x03, 0x01, 0xCC, 0xFF,
-                                    0xCC, 0xFF, 0xCC, 0xFF, 0xCC, 0xFF,
-                                ]); // empty raw chunk, from wiki.vg
-                            }
-                            let response = PacketBuilder::new(0x21)
-                                .with_i32(x - 2) // chunk x
-                                .with_i32(z - 2) // chunk z
-                                .with_nbt(&NamedTag::new(
-                                    "",
-                                    NBT::Compound(vec![NamedTag::new(
-                                        "MOTION_BLOCKING",
-                                        NBT::LongArray(vec![0; 36]),
-                                    )]),
-                                ))
-                                .with_var_int(data.len() as _) // size of data
-                                .with_raw_bytes(&data)
-                                .with_var_int(0) // no. of block entities
-                                .with_bool(true) // trust edges for light updates
-                                .with_var_int(0) // bit set for sky light mask (length 0 = no data)
-                                .with_var_int(0) // bit set for block light mask
-                                .with_var_int(0) // bit set for empty sky light mask
-                                .with_var_int(0) // bit set for empty block light mask
-                                .with_var_int(0) // no. of sky lights
-                                .with_var_int(0) // no. of block lights
-                                .build();
-
-                            stream.write_all(&response).await?;
-                            stream.flush().await?;
-                        }
-                    }
+        self.send_packet(stream, response).await?;
 
-                    // Send synchronize player position
-                    let response = PacketBuilder::new(0x39)
-                        .with_double(0.0) // x
-                        .with_double(0.0) // y
-                        .with_double(0.0) // z
-                        .with_float(0.0) // yaw
-                        .with_float(0.0) // pitch
-                        .with_u8(0) // flags
-                        .with_var_int(42) // teleport id
-                        .with_bool(false) // dismount vehicle
-                        .build();
+        // Send set center chunk
+        let response = PacketBuilder::new(self.ids.set_center_chunk)
+            .with_var_int(0) // x
+            .with_var_int(0) // z
+            .build();
 
-                    stream.write_all(&response).await?;
-                    stream.flush().await?;
+        self.send_packet(stream, response).await?;
 
-                    log::info!("{} [{}] has connected to the login server.", self.username, self.real_address);
+        let world = World::new(&self.config.world.platforms);
+        self.send_chunks(stream, self.config.world.view_distance, &world).await?;
 
-                    match self.context.lock().await.player_exists(&self.username).await {
-                        Ok(b) => match b {
-                            false => {
-                                let response = PacketBuilder::new(0x5d)
-                                    .with_string("{\"text\":\"/register [password] [password]\"}")
-                                    .build();
+        log::info!("{} [{}] has connected to the login server.", self.username, self.real_address);
+        self.joined_at = Some(Instant::now());
 
-                                stream.write_all(&response).await?;
-                                stream.flush().await?;
-                            }
-                            true => {
-                                let response = PacketBuilder::new(0x5d)
-                                    .with_string("{\"text\":\"/login [password]\"}")
-                                    .build();
+        self.send_welcome(stream).await
+    }
 
-                                stream.write_all(&response).await?;
-                                stream.flush().await?;
-                            }
-                        },
-                        Err(e) => {
-                            log::error!("Database error: {:?}", e);
+    /// Sends the `welcome_lines` that are due, one every `welcome_delay_ms`. After the last
+    /// one, moves on to the queue or the login.
+    async fn send_welcome(&mut self, stream: &mut ClientStream) -> Result<()> {
+        let config = Arc::clone(&self.config);
+        let delay = Duration::from_millis(config.welcome_delay_ms);
 
-                            return self
-                                .kick(stream, "Database error. Please contact one of the admins.")
-                                .await;
-                        }
-                    }
+        while let Some(line) = config.welcome_lines.get(self.welcome_sent) {
+            self.send_chat(stream, line).await?;
+            self.welcome_sent += 1;
 
-                    stream.write_all(&response).await?;
-                    stream.flush().await?;
+            if !delay.is_zero() && self.welcome_sent < config.welcome_lines.len() {
+                self.next_welcome_at = Some(Instant::now() + delay);
+                return Ok(());
+            }
+        }
+        self.next_welcome_at = None;
 
-                    // Switch over to the "play" state
-                    self.state = 3;
+        if self.queued.is_some() {
+            return self.show_queue_position(stream).await;
+        }
+        self.start_login(stream).await
+    }
+
+    /// Hands out the captcha and resource pack, then logs the player in from a saved session
+    /// or prompts them to log in.
+    async fn start_login(&mut self, stream: &mut ClientStream) -> Result<()> {
+        if self.config.captcha.enabled {
+            let code = captcha::generate_code();
+            self.send_packet(stream, captcha::flying_abilities_packet(self.ids)).await?;
+            self.send_packet(stream, captcha::map_data_packet(self.ids, &captcha::render(&code))).await?;
+            self.send_packet(stream, captcha::map_item_packet(self.ids, true)).await?;
+            self.send_chat(stream, &self.messages().captcha_prompt).await?;
+            self.captcha = Some(code);
+        }
+
+        if let Some(pack) = &self.config.resource_pack {
+            let prompt = pack.prompt.as_deref().map(chat::legacy_to_chat_json);
+            let packet = resource_pack_packet(self.ids, &pack.url, &pack.hash, pack.forced, prompt.as_deref());
+            self.send_packet(stream, packet).await?;
+        }
+
+        if self.config.sessions.enabled {
+            let result = self.db().await.has_session(&self.username, &self.real_address).await;
+
+            match result {
+                Ok(true) => {
+                    log::info!("{} [{}] has a saved session.", self.username, self.real_address);
+                    let delay = Duration::from_secs(self.config.sessions.forward_delay_secs);
+                    self.session_login_at = Some(Instant::now() + delay);
+                    return Ok(());
                 }
-                _ => ()
-            },
-            3 => {
-                match packet_id {
-                    0x20 => {
-                        let payload = buffer.read_i32::<BigEndian>().await?;
+                Ok(false) => {}
+                Err(e) => return self.kick_db_error(stream, e).await,
+            }
+        }
 
-                        stream
-                            .write_all(&PacketBuilder::new(0x2f).with_i32(payload).build())
-                            .await?;
-                        stream.flush().await?;
-                    }
-                    0x12 => {
-                        let payload = buffer.read_i64::<BigEndian>().await?;
+        match self.db().await.player_exists(&self.username).await {
+            Ok(registered) => {
+                self.registered = Some(registered);
+                self.send_login_prompt(stream, registered).await?;
+            }
+            Err(e) => return self.kick_db_error(stream, e).await,
+        }
 
-                        stream
-                            .write_all(&PacketBuilder::new(0x20).with_i64(payload).build())
-                            .await?;
-                        stream.flush().await?;
-                    }
-                    0x4 => {
-                        let command = protocol::read_string(&mut buffer).await?;
-                        let args = command.split(" ").collect::<Vec<&str>>();
-                        let command = args[0];
-
-                        match command {
-                            "login" => {
-                                if args.len() != 2 {
-                                    return self
-                                        .kick(stream, "Invalid syntax. Usage: /login [password]")
-                                        .await;
-                                }
+        if self.config.login.timeout_secs > 0 {
+            self.login_deadline =
+                Some(Instant::now() + Duration::from_secs(self.config.login.timeout_secs));
+        }
 
-                                let password = args[1];
-
-                                match self
-                                    .context
-                                    .lock()
-                                    .await
-                                    .authenticate(&self.username, password)
-                                    .await
-                                {
-                                    Ok(success) => match success {
-                                        false => {
-                                            log::warn!("{} [{}] has specified an incorrect password.", self.username, self.real_address);
-                                            return self
-                                                .kick(
-                                                    stream,
-                                                    "Invalid password or user not registered.",
-                                                )
-                                                .await;
-                                        }
-                                        true => {
-                                            log::info!("{} [{}] has successfully authenticated.", self.username, self.real_address);
-
-                                            stream
-                                                .write_all(
-                                                    &PacketBuilder::new(0x16)
-                                                        .with_string("BungeeCord")
-                                                        .with_raw_bytes(b"\x00\x07Connect")
-                                                        .with_raw_bytes(b"\x00\x04main")
-                                                        .build(),
-                                                )
-                                                .await?;
-                                            stream.flush().await?;
-                                        }
-                                    },
-                                    Err(e) => {
-                                        log::error!("Database error: {:?}", e);
-
-                                        return self
-                                            .kick(
-                                                stream,
-                                                "Database error. Please contact one of the admins.",
-                                            )
-                                            .await;
-                                    }
-                                }
-                            }
-                            "register" => {
-                                if args.len() != 3 {
-                                    return self.kick(stream, "Invalid syntax. Usage: /register [password] [password]").await;
-                                }
+        Ok(())
+    }
 
-                                let password = args[1];
-                                if args[1] != args[2] {
-                                    if args.len() != 2 {
-                                        return self.kick(stream, "Passwords do not match.").await;
-                                    }
-                                }
+    /// Sends the chunks around spawn one at a time, letting other connections run in between.
+    async fn send_chunks(&self, stream: &mut ClientStream, view_distance: i32, world: &World) -> Result<()> {
+        let mut data = Vec::new();
+        for (cx, cz) in world::chunks_around_spawn(view_distance) {
+            self.send_packet(stream, world::build_chunk_packet(self.ids, cx, cz, world, &mut data)).await?;
+            tokio::task::yield_now().await;
+        }
+        Ok(())
+    }
 
-                                match self.context.lock().await.register(&self.username, password).await {
-                                    Ok(success) => match success {
-                                        false => {
-                                            log::warn!("{} [{}] attempted double registration.", self.username, self.real_address);
-                                            return self
-                                                .kick(stream, "This user is already registered.")
-                                                .await;
-                                        }
-                                        true => {
-                                            log::info!("{} [{}] has successfully registered.", self.username, self.real_address);
-                                            stream
-                                                .write_all(
-                                                    &PacketBuilder::new(0x16)
-                                                        .with_string("BungeeCord")
-                                                        .with_raw_bytes(b"\x00\x07Connect")
-                                                        .with_raw_bytes(b"\x00\x04main")
-                                                        .build(),
-                                                )
-                                                .await?;
-                                            stream.flush().await?;
-                                        }
-                                    },
-                                    Err(e) => {
-                                        log::error!("Database error: {:?}", e);
-
-                                        return self
-                                            .kick(
-                                                stream,
-                                                "Database error. Please contact one of the admins.",
-                                            )
-                                            .await;
-                                    }
-                                }
-                            }
-                            _ => {
-                                return self.kick(stream, "Invalid command.").await;
-                            }
-                        }
-                    }
-                    _ => ()
+    /// Shows /login or /register as a title, depending on whether the player has an account.
+    async fn send_login_prompt(&self, stream: &mut ClientStream, registered: bool) -> Result<()> {
+        let prompt = match (registered, self.config.login.require_email) {
+            (true, _) => &self.messages().login_prompt,
+            (false, true) => &self.messages().register_email_prompt,
+            (false, false) => &self.messages().register_prompt,
+        };
+        self.send_packet(stream, title_packet(self.ids, &chat::legacy_to_chat_json(prompt))).await
+    }
+
+    /// The server list entry. Supported clients see their own protocol echoed back, everyone
+    /// else the newest supported one, so the list marks their client as outdated. Status-only
+    /// pings get the newest one too. With ping passthrough the backend's entry is used instead
+    /// while it answers, with the protocol chosen the same way.
+    async fn status_response(&self) -> Result<String> {
+        let versions = &self.config.versions;
+        let protocol = match codec::negotiate(self.protocol_version, versions) {
+            Some(_) => Some(self.protocol_version),
+            None => codec::newest_protocol(versions),
+        };
+
+        let passthrough = &self.config.ping_passthrough;
+        if passthrough.enabled {
+            let cache = Arc::clone(&self.context.lock().await.status_cache);
+            if let Some(status) = cache.get(passthrough, self.protocol_version).await {
+                // The cached entry echoes whichever client's ping fetched it
+                let mut status = json::parse(&status)?;
+                if let Some(protocol) = protocol {
+                    status["version"]["protocol"] = protocol.into();
                 }
+                return Ok(json::stringify(status));
             }
-            _ => {
-                return Err(anyhow!("Unknown connection state."))
+        }
+
+        let mut status = json::parse(include_str!("status_response.json"))?;
+        if let Some(protocol) = protocol {
+            status["version"]["protocol"] = protocol.into();
+        }
+        let version = codec::supported_versions(versions);
+        status["version"]["name"] = version.as_str().into();
+
+        let online = players::online(&self.players().await).await;
+        let motd = &self.config.motd;
+        let (online_count, max) = (online.len().to_string(), motd.max_players.to_string());
+        let values = [("online", online_count.as_str()), ("max", max.as_str()), ("version", version.as_str())];
+
+        let mut description = json::object! { text: "", extra: [] };
+        for (i, line) in motd.lines.iter().enumerate() {
+            if i > 0 {
+                description["extra"].push("\n")?;
+            }
+            let mut component = chat::legacy_to_component(&chat::fill_placeholders(line, &values));
+            if component["color"].is_null() {
+                component["color"] = "white".into();
             }
+            description["extra"].push(component)?;
         }
+        status["description"] = description;
+
+        status["players"]["max"] = motd.max_players.into();
+        status["players"]["online"] = online.len().into();
+        status["players"]["sample"] = online
+            .iter()
+            .take(12)
+            .map(|(name, uuid)| json::object! { name: name.as_str(), id: uuid.to_string() })
+            .collect::<Vec<_>>()
+            .into();
+
+        Ok(json::stringify(status))
+    }
+
+    /// Kicks a client whose version players aren't allowed to join with, listing the ones
+    /// they are.
+    async fn reject_version(&self, stream: &mut ClientStream) -> Result<()> {
+        let versions = codec::supported_versions(&self.config.versions);
+        let reason = chat::fill_placeholders(&self.messages().unsupported_version, &[("versions", &versions)]);
+        self.kick(stream, reason, None).await
+    }
 
+    /// Packets the limbo has no use for are ignored, except during status and login with
+    /// `strict_protocol`, where anything unexpected drops the connection.
+    async fn unknown_packet(&self, stream: &mut ClientStream, id: i32) -> Result<()> {
+        log::trace!("Ignoring packet {:#04x} in state {} from {}", id, self.state, self.peer);
+
+        if self.config.strict_protocol && matches!(self.state, 1 | 2) {
+            log::warn!("{} [{}] sent unexpected packet {:#04x} in state {}.", self.username, self.real_address, id, self.state);
+            return self.kick(stream, &self.messages().unexpected_packet, None).await;
+        }
         Ok(())
     }
 
-    pub async fn kick(&self, stream: &mut TcpStream, reason: impl Into<String>) -> Result<()> {
-        let reason = reason.into();
-        let response = PacketBuilder::new(0x19)
-            .with_string(&format!("{{\"text\":\"{reason}\"}}"))
+    /// Looks up and runs a chat command. Syntax errors kick players who haven't logged in
+    /// yet, as bots tend to send them, and only earn authenticated players a usage hint.
+    async fn run_command(&mut self, stream: &mut ClientStream, line: &str) -> Result<()> {
+        if self.queued.is_some() {
+            return self.send_chat(stream, &self.messages().wait_for_slot).await;
+        }
+        if self.next_welcome_at.is_some() {
+            return self.send_chat(stream, &self.messages().welcome_first).await;
+        }
+
+        let (name, args) = match line.split_once(' ') {
+            Some((name, args)) => (name, args.split(' ').collect::<Vec<&str>>()),
+            None => (line, Vec::new()),
+        };
+
+        let command = self.context.lock().await.commands.get(name);
+        let Some(command) = command else {
+            return self.kick(stream, &self.messages().invalid_command, None).await;
+        };
+
+        if command.bot_checked() {
+            if self.config.login.require_movement && !self.has_moved {
+                return self.send_chat(stream, &self.messages().move_first).await;
+            }
+            if self.captcha.is_some() {
+                return self.send_chat(stream, &self.messages().captcha_first).await;
+            }
+        }
+
+        if command.admin_only() && !(self.authenticated && self.is_admin()) {
+            return self.send_chat(stream, &self.messages().insufficient_permissions).await;
+        }
+
+        let outcome = match command.arity().contains(&args.len()) {
+            true => command.execute(self, stream, &args).await?,
+            false => CommandOutcome::InvalidSyntax,
+        };
+
+        match outcome {
+            CommandOutcome::Done => Ok(()),
+            CommandOutcome::InvalidSyntax => {
+                let message = chat::fill_placeholders(&self.messages().invalid_syntax, &[("usage", command.usage())]);
+                match self.authenticated {
+                    true => self.send_chat(stream, &message).await,
+                    false => self.kick(stream, message, None).await,
+                }
+            }
+            CommandOutcome::Kick(reason) => self.kick(stream, reason, None).await,
+            CommandOutcome::KickRetry(reason, retry_after) => self.kick(stream, reason, Some(retry_after)).await,
+            CommandOutcome::DbError(e) => self.kick_db_error(stream, e).await,
+        }
+    }
+
+    pub async fn send_chat(&self, stream: &mut ClientStream, text: &str) -> Result<()> {
+        self.send_packet(stream, system_message(self.ids, text)).await
+    }
+
+    pub async fn kick_db_error(&self, stream: &mut ClientStream, error: anyhow::Error) -> Result<()> {
+        log::error!("Database error: {:?}", error);
+        self.metrics().await.db_errors_total.inc();
+
+        let retry_after = self.config.database.reconnect_after_secs;
+        self.kick(stream, &self.messages().database_error, (retry_after > 0).then(|| Duration::from_secs(retry_after)))
+            .await
+    }
+
+    /// `/2fa enable [password]`: generates a secret and waits for the player to confirm it
+    /// with a code before storing it. Only offered to players who are already logged in.
+    async fn begin_totp_setup(&mut self, stream: &mut ClientStream, password: &str) -> Result<()> {
+        let result = self.db().await.authenticate(&self.username, password).await;
+
+        match result {
+            Ok(false) => {
+                log::warn!("{} [{}] has specified an incorrect password.", self.username, self.real_address);
+                return self.kick(stream, &self.messages().invalid_password, None).await;
+            }
+            Ok(true) => {}
+            Err(e) => return self.kick_db_error(stream, e).await,
+        }
+
+        let has_totp = self.db().await.has_totp(&self.username).await;
+        match has_totp {
+            Ok(true) => return self.send_chat(stream, &self.messages().totp_already_enabled).await,
+            Ok(false) => {}
+            Err(e) => return self.kick_db_error(stream, e).await,
+        }
+
+        let secret = totp::generate_secret();
+        let encoded = totp::encode_secret(&secret);
+        self.pending_totp = Some(secret);
+
+        self.send_chat(stream, &chat::fill_placeholders(&self.messages().totp_secret, &[("secret", &encoded)]))
+            .await?;
+        self.send_chat(stream, &self.messages().totp_confirm_prompt).await
+    }
+
+    /// `/2fa [code]`: confirms a pending setup, or finishes a login that is waiting for a code.
+    async fn submit_totp_code(&mut self, stream: &mut ClientStream, code: &str) -> Result<()> {
+        if let Some(secret) = &self.pending_totp {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            if !totp::verify(secret, code, now) {
+                return self.send_chat(stream, &self.messages().totp_retry).await;
+            }
+
+            let result = self.db().await.enable_totp(&self.username, secret).await;
+            if let Err(e) = result {
+                return self.kick_db_error(stream, e).await;
+            }
+
+            log::info!("{} [{}] has enabled two-factor authentication.", self.username, self.real_address);
+            self.pending_totp = None;
+            return self.send_chat(stream, &self.messages().totp_enabled).await;
+        }
+
+        if !self.awaiting_totp {
+            return self.send_chat(stream, &self.messages().nothing_to_verify).await;
+        }
+
+        let result = self.db().await.verify_totp(&self.username, code).await;
+        match result {
+            Ok(true) => {
+                log::info!("{} [{}] has successfully authenticated.", self.username, self.real_address);
+                self.awaiting_totp = false;
+                self.on_authenticated(stream).await
+            }
+            Ok(false) => {
+                log::warn!("{} [{}] has specified an incorrect 2FA code.", self.username, self.real_address);
+                self.metrics().await.logins_failed_total.inc();
+                self.kick(stream, &self.messages().invalid_totp_code, None).await
+            }
+            Err(e) => self.kick_db_error(stream, e).await,
+        }
+    }
+
+    /// Called once the player has proven who they are. Remembers the session if enabled,
+    /// then completes the login.
+    pub async fn on_authenticated(&mut self, stream: &mut ClientStream) -> Result<()> {
+        if self.config.sessions.enabled {
+            let ttl = Duration::from_secs(self.config.sessions.ttl_secs);
+            let result = self.db().await.create_session(&self.username, &self.real_address, ttl).await;
+
+            if let Err(e) = result {
+                return self.kick_db_error(stream, e).await;
+            }
+        }
+
+        self.complete_login(stream).await
+    }
+
+    /// Either hands the player off to the backend or, with transfers disabled, keeps them
+    /// in the limbo as an authenticated player.
+    pub async fn complete_login(&mut self, stream: &mut ClientStream) -> Result<()> {
+        self.authenticated = true;
+        self.login_deadline = None;
+        self.session_login_at = None;
+        self.handle.set_authenticated(self.uuid);
+        if let Some(joined_at) = self.joined_at {
+            log::info!(
+                "{} [{}] logged in {:.1}s after joining.",
+                self.username,
+                self.real_address,
+                joined_at.elapsed().as_secs_f64()
+            );
+        }
+        self.metrics().await.logins_success_total.inc();
+        self.events().await.on_login(&self.username, self.peer).await;
+
+        if self.config.backend.transfer {
+            let backend = &self.config.backend;
+            let server = self
+                .context
+                .lock()
+                .await
+                .backends
+                .select(&backend.servers, backend.strategy, &self.username)
+                .map(str::to_string);
+
+            let Some(server) = server else {
+                log::error!("No backend servers are configured.");
+                return self.kick(stream, &self.messages().no_backend, None).await;
+            };
+
+            log::info!("Sending {} [{}] to {}.", self.username, self.real_address, server);
+
+            let packet = bungee::bungee_connect_packet(&server, self.protocol_version, self.ids)?;
+            return self.send_packet(stream, packet).await;
+        }
+
+        self.send_chat(stream, &self.config.backend.welcome_message).await
+    }
+
+    /// There are no blocks in the limbo, so with `void.keep_still` a falling player is put
+    /// back at spawn on every tick instead of falling until they hit the void threshold.
+    async fn keep_player_still(&mut self, stream: &mut ClientStream) -> Result<()> {
+        if !self.config.void.keep_still || !self.falling {
+            return Ok(());
+        }
+
+        self.falling = false;
+        self.teleport_to_spawn(stream).await
+    }
+
+    pub async fn teleport_to_spawn(&self, stream: &mut ClientStream) -> Result<()> {
+        // Send synchronize player position
+        let response = PacketBuilder::new(self.ids.synchronize_position)
+            .with_double(0.0) // x
+            .with_double(SPAWN_Y) // y
+            .with_double(0.0) // z
+            .with_float(0.0) // yaw
+            .with_float(0.0) // pitch
+            .with_u8(0) // flags
+            .with_var_int(42) // teleport id
+            .with_bool(false) // dismount vehicle
             .build();
 
-        stream.write_all(&response).await?;
-        stream.flush().await?;
+        self.send_packet(stream, response).await
+    }
+
+    /// Disconnects the player. With `retry_after`, the reason is followed by a hint to
+    /// reconnect once that time has passed, for kicks that aren't the player's fault.
+    pub async fn kick(
+        &self,
+        stream: &mut ClientStream,
+        reason: impl Into<String>,
+        retry_after: Option<Duration>,
+    ) -> Result<()> {
+        let reason = reason.into();
+
+        // Handshaking and status have no disconnect packet, the connection is just dropped
+        if self.state >= 2 {
+            let message = match retry_after {
+                Some(wait) => {
+                    let seconds = wait.as_secs_f64().ceil().to_string();
+                    chat::fill_placeholders(&self.messages().retry_hint, &[("reason", &reason), ("seconds", &seconds)])
+                }
+                None => reason.clone(),
+            };
+            let json = chat::legacy_to_chat_json(&message);
+            self.send_packet(stream, disconnect_packet(self.state, self.ids, &json)).await?;
+        }
 
-        return Err(anyhow!(
+        Err(anyhow!(
             "Kicked player {} [{}] with reason: \"{}\"",
             self.username,
             self.real_address,
             reason
-        ));
+        ))
     }
 
-    pub async fn connect(mut self, mut stream: tokio::net::TcpStream) {
+    /// Whether the player has just become slow to log in: they joined more than
+    /// `login.slow_login_secs` before `now` and still haven't logged in. True only once.
+    fn check_slow_login(&mut self, now: Instant) -> bool {
+        let threshold = Duration::from_secs(self.config.login.slow_login_secs);
+        let Some(joined_at) = self.joined_at else {
+            return false;
+        };
+        if threshold.is_zero() || self.authenticated || self.slow_login || now.duration_since(joined_at) < threshold {
+            return false;
+        }
+
+        self.slow_login = true;
+        true
+    }
+
+    /// Runs once a second alongside packet handling. Counts down the time left to log in on
+    /// the action bar and kicks the player once it runs out.
+    pub async fn tick(&mut self, stream: &mut ClientStream) -> Result<()> {
+        self.keep_player_still(stream).await?;
+
+        if self.queued.is_some() {
+            return self.check_queue(stream).await;
+        }
+
+        if self.session_login_at.is_some_and(|at| Instant::now() >= at) {
+            log::info!("{} [{}] has been logged in from a saved session.", self.username, self.real_address);
+            return self.complete_login(stream).await;
+        }
+
+        if self.check_slow_login(Instant::now()) {
+            log::warn!(
+                "{} [{}] still hasn't logged in {}s after joining.",
+                self.username,
+                self.real_address,
+                self.config.login.slow_login_secs
+            );
+            self.metrics().await.slow_logins_total.inc();
+        }
+
+        let Some(deadline) = self.login_deadline else {
+            return Ok(());
+        };
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            log::warn!("{} [{}] did not log in in time.", self.username, self.real_address);
+            return self.kick(stream, &self.messages().login_timeout, None).await;
+        }
+
+        let seconds = remaining.as_secs_f64().ceil().to_string();
+        let text = chat::fill_placeholders(&self.messages().login_countdown, &[("seconds", &seconds)]);
+        self.send_packet(stream, overlay_message(self.ids, &text)).await
+    }
+
+    /// Lets a connection waiting in line in once a slot has been handed to it, and otherwise
+    /// shows the player their new place in line. Players who have already spawned and been
+    /// welcomed get the login prompt right away, the others once they are.
+    async fn check_queue(&mut self, stream: &mut ClientStream) -> Result<()> {
+        let Some(ticket) = self.queued.take() else {
+            return Ok(());
+        };
+
+        match ticket.admit() {
+            Ok(slot) => {
+                log::debug!("{} [{}] has been given a slot.", self.username, self.peer);
+                self.slot = Some(slot);
+                match (self.joined_at, self.next_welcome_at) {
+                    (Some(_), None) => self.start_login(stream).await,
+                    _ => Ok(()),
+                }
+            }
+            Err(ticket) => {
+                self.queued = Some(ticket);
+                self.show_queue_position(stream).await
+            }
+        }
+    }
+
+    /// Shows a player who has spawned their place in line on the action bar. It fades after
+    /// a few seconds, so this is repeated every tick.
+    async fn show_queue_position(&self, stream: &mut ClientStream) -> Result<()> {
+        let Some(position) = self.queued.as_ref().and_then(Ticket::position) else {
+            return Ok(());
+        };
+        if self.joined_at.is_none() {
+            return Ok(());
+        }
+
+        let text = chat::fill_placeholders(&self.messages().queue_position, &[("position", &position.to_string())]);
+        self.send_packet(stream, overlay_message(self.ids, &text)).await
+    }
+
+    /// Builds the packet for something another task queued for this connection.
+    fn outbound_packet(&self, outbound: &Outbound) -> Vec<u8> {
+        match outbound {
+            Outbound::Message(text) => system_message(self.ids, text),
+            Outbound::Disconnect(reason) => disconnect_packet(3, self.ids, &chat::legacy_to_chat_json(reason)),
+        }
+    }
+
+    /// Writes what other tasks have queued, such as the Disconnect packet of a kick, before
+    /// a cancelled connection is closed.
+    async fn flush_outbound(&mut self, stream: &mut ClientStream) {
+        while let Ok(outbound) = self.outbound.try_recv() {
+            if let Err(e) = self.send_packet(stream, self.outbound_packet(&outbound)).await {
+                log::debug!("Failed to write queued packets to {}: {:?}", self.peer, e);
+                return;
+            }
+        }
+    }
+
+    /// When a connection still in the handshake or status state is dropped, if the
+    /// `rate_limit.status_timeout_secs` limit is on.
+    fn status_deadline(&self) -> Option<Instant> {
+        let timeout = self.config.rate_limit.status_timeout_secs;
+        (matches!(self.state, 0 | 1) && timeout > 0).then(|| self.connected_at + Duration::from_secs(timeout))
+    }
+
+    pub async fn connect(mut self, mut stream: TcpStream) {
+        if self.config.proxy_protocol.enabled {
+            let header = tokio::time::timeout(
+                Duration::from_secs(5),
+                protocol::proxy::read_header(&mut stream),
+            )
+            .await;
+
+            match header {
+                Ok(Ok(Some(address))) => self.peer = address,
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => {
+                    log::warn!("Dropping connection from {}: {:?}", self.peer, e);
+                    return;
+                }
+                Err(_) => {
+                    log::warn!("Dropping connection from {}: no PROXY header received", self.peer);
+                    return;
+                }
+            }
+        }
+
+        let allowed = self.context.lock().await.connections.allow(self.peer.ip(), Instant::now());
+        if !allowed {
+            log::warn!("Refusing connection from {}: too many connections.", self.peer);
+            return;
+        }
+
+        if let Some(dir) = &self.config.capture_dir {
+            match Capture::create(dir, self.peer).await {
+                Ok(capture) => {
+                    log::debug!("Capturing packets of {} to {}", self.peer, capture.path().display());
+                    self.capture = Some(capture);
+                }
+                Err(e) => log::error!("Failed to start capturing packets of {}: {:?}", self.peer, e),
+            }
+        }
+
+        self.events().await.on_connect(self.peer).await;
+        let mut stream = BufWriter::new(stream);
+
+        let mut ticker = tokio::time::interval(Duration::from_secs(1));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // One receiver for waiting between packets and one for while a packet is read
+        let mut cancelled = self.handle.cancelled();
+        let mut cancelled_while_reading = self.handle.cancelled();
+
         loop {
-            match self.receive_packet(&mut stream).await {
+            let status_deadline = self.status_deadline();
+            // Wait for data by peeking rather than racing a read directly, so a tick never
+            // interrupts a packet halfway through. `readable()` can't be used here, it stays
+            // ready until a read would block, which read_exact never does
+            let mut probe = [0u8; 1];
+            let result = tokio::select! {
+                peeked = stream.get_ref().peek(&mut probe) => match peeked {
+                    Ok(0) => {
+                        log::debug!("{} [{}] disconnected.", self.username, self.peer);
+                        break;
+                    }
+                    // A packet that never finishes arriving mustn't keep a cancelled connection
+                    // open. Dropping the read halfway is fine, the stream isn't used again
+                    Ok(_) => tokio::select! {
+                        result = self.receive_packet(&mut stream) => result,
+                        _ = cancelled_while_reading.wait() => {
+                            self.flush_outbound(&mut stream).await;
+                            break;
+                        }
+                        _ = sleep_until_deadline(status_deadline) => {
+                            log::debug!("Dropping {}: it stayed in the status state too long.", self.peer);
+                            break;
+                        }
+                    },
+                    Err(e) => Err(e.into()),
+                },
+                _ = ticker.tick() => self.tick(&mut stream).await,
+                Some(outbound) = self.outbound.recv() => match outbound {
+                    Outbound::Message(text) => self.send_packet(&mut stream, system_message(self.ids, &text)).await,
+                    outbound @ Outbound::Disconnect(_) => {
+                        self.state = -1;
+                        self.send_packet(&mut stream, self.outbound_packet(&outbound)).await
+                    }
+                },
+                _ = cancelled.wait() => {
+                    self.flush_outbound(&mut stream).await;
+                    break;
+                }
+                _ = sleep_until_deadline(status_deadline) => {
+                    log::debug!("Dropping {}: it stayed in the status state too long.", self.peer);
+                    break;
+                }
+                _ = line_moved(self.queued.as_mut()) => self.check_queue(&mut stream).await,
+                _ = sleep_until_deadline(self.next_welcome_at) => self.send_welcome(&mut stream).await,
+            };
+
+            if let Err(e) = stream.flush().await {
+                log::debug!("Failed to write to {}: {:?}", self.peer, e);
+                break;
+            }
+
+            match result {
                 Ok(_) => {}
                 Err(e) => {
-                    log::error!("{:?}", e);
+                    match e.downcast_ref::<ProtocolError>() {
+                        Some(ProtocolError::Closed) => {
+                            log::debug!("{} [{}] disconnected.", self.username, self.peer)
+                        }
+                        Some(ProtocolError::UnexpectedEof) => {
+                            log::warn!("{} [{}] disconnected in the middle of a packet.", self.username, self.peer)
+                        }
+                        _ => log::error!("{:?}", e),
+                    }
                     break;
                 }
             }
@@ -495,11 +1290,141 @@ impl State {
                 break;
             }
         }
+        // Packets queued right before leaving the loop, e.g. those of a kick from another task
+        if let Err(e) = stream.flush().await {
+            log::debug!("Failed to write to {}: {:?}", self.peer, e);
+        }
+
+        let players = self.players().await;
+        let joined = {
+            let mut players = players.lock().await;
+            let joined = players
+                .get(&self.username)
+                .is_some_and(|handle| handle.same_connection(&self.handle));
+            if joined {
+                players.remove(&self.username);
+            }
+            joined
+        };
+
+        if joined {
+            self.events().await.on_disconnect(&self.username).await;
+        }
+
+        // Status pings and dropped handshakes would drown out the connections that matter
+        let duration = self.connected_at.elapsed().as_secs_f64();
+        match self.joined_at {
+            Some(_) => log::info!("{} [{}] was connected for {:.1}s.", self.username, self.real_address, duration),
+            None => log::debug!("Connection from {} closed after {:.1}s.", self.peer, duration),
+        }
+    }
+}
+
+/// Waits until `deadline`, or forever without one.
+async fn sleep_until_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the line `ticket` is in to move, or forever without one.
+async fn line_moved(ticket: Option<&mut Ticket>) {
+    match ticket {
+        Some(ticket) => ticket.moved().await,
+        None => std::future::pending().await,
     }
 }
 
+/// Height players spawn and are teleported back to.
+const SPAWN_Y: f64 = 0.0;
+
+/// Builds the Disconnect packet for the given connection state. Login and play use
+/// different packet IDs, and sending the wrong one leaves the client with a decode error.
+pub fn disconnect_packet(state: i32, ids: &PlayIds, reason_json: &str) -> Vec<u8> {
+    let id = match state {
+        2 => 0x00, // Disconnect (login)
+        _ => ids.disconnect,
+    };
+
+    PacketBuilder::new(id).with_string(reason_json).build()
+}
+
+/// System Chat Message shown in the chat box. `text` may use `&` color codes.
+pub fn system_message(ids: &PlayIds, text: &str) -> Vec<u8> {
+    system_chat_packet(ids, text, false)
+}
+
+/// System Chat Message shown above the hotbar instead of in the chat box.
+pub fn overlay_message(ids: &PlayIds, text: &str) -> Vec<u8> {
+    system_chat_packet(ids, text, true)
+}
+
+fn system_chat_packet(ids: &PlayIds, text: &str, overlay: bool) -> Vec<u8> {
+    PacketBuilder::new(ids.system_chat)
+        .with_string(&chat::legacy_to_chat_json(text))
+        .with_bool(overlay)
+        .build()
+}
+
+/// Resource Pack (1.19.2). `prompt_json` is shown in the client's download prompt.
+pub fn resource_pack_packet(ids: &PlayIds, url: &str, hash: &str, forced: bool, prompt_json: Option<&str>) -> Vec<u8> {
+    PacketBuilder::new(ids.resource_pack)
+        .with_string(url)
+        .with_string(hash)
+        .with_bool(forced)
+        .with_optional(prompt_json, |p, prompt| p.with_string(prompt))
+        .build()
+}
+
+/// Set Title Text, shown in large letters in the middle of the screen.
+pub fn title_packet(ids: &PlayIds, json: &str) -> Vec<u8> {
+    PacketBuilder::new(ids.set_title_text).with_string(json).build()
+}
+
+/// Creates an account, or sets a new password for one, without starting the server. The
+/// database must not be in use by a running server unless it is a remote one.
+async fn account_command(command: &str, args: &[String]) -> Result<()> {
+    let [name, password] = args else {
+        eprintln!("Usage: ./void-rs {command} [name] [password]");
+        return Err(anyhow!("expected a name and a password"));
+    };
+    if !players::valid_username(name) {
+        return Err(anyhow!("{name:?} is not a valid username"));
+    }
+
+    let shared_config = Arc::new(SharedConfig::load(CONFIG_PATH)?);
+    let context = Context::new(shared_config, Arc::default()).await?;
+
+    if command == "register" {
+        match context.db.register(name, password, None, None, 0).await? {
+            Registration::Created => println!("Registered {name}."),
+            Registration::AlreadyRegistered => return Err(anyhow!("{name} is already registered")),
+            Registration::TooManyAccounts => return Err(anyhow!("too many accounts to register {name}")),
+        }
+    } else if context.db.reset_password(name, password).await? {
+        println!("Reset the password of {name}.");
+    } else {
+        return Err(anyhow!("{name} is not registered"));
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    if std::env::args().nth(1).as_deref() == Some("--replay") {
+        let Some(path) = std::env::args().nth(2) else {
+            eprintln!("Usage: ./void-rs --replay [file]");
+            return Err(anyhow!("unspecified capture file"));
+        };
+        return capture::print_replay(path);
+    }
+
+    if let Some(command @ ("register" | "reset")) = std::env::args().nth(1).as_deref() {
+        let args = std::env::args().skip(2).collect::<Vec<_>>();
+        return account_command(command, &args).await;
+    }
+
     let console_dispatch = fern::Dispatch::new()
         .format(|out, message, record| {
             let colors = fern::colors::ColoredLevelConfig::new()
@@ -535,31 +1460,113 @@ async fn main() -> Result<()> {
         .chain(file_dispatch)
         .apply()?;
 
-    let socket = match std::env::args().nth(1) {
-        Some(socket) => socket,
-        None => {
-            eprintln!("You must specify an address and port.");
-            eprintln!("Usage: ./void-rs [ip:port]");
-            return Err(anyhow!("unspecified socket address"));
-        }
-    };
+    let shared_config = Arc::new(SharedConfig::load(CONFIG_PATH)?);
+    let config = shared_config.get();
 
-    let listener = TcpListener::bind(&socket).await?;
-    let context = Context {
-        db: db::init_db().await?,
+    // An address on the command line takes the place of the ones in the config
+    let addresses = match std::env::args().nth(1) {
+        Some(address) => vec![address],
+        None => config.bind.clone(),
     };
+    if addresses.is_empty() {
+        eprintln!("You must specify an address and port, or set `bind` in config.toml.");
+        eprintln!("Usage: ./void-rs [ip:port]\n       ./void-rs --replay [file]");
+        eprintln!("       ./void-rs register|reset [name] [password]");
+        return Err(anyhow!("unspecified socket address"));
+    }
+
+    let mut listeners = Vec::with_capacity(addresses.len());
+    for address in &addresses {
+        listeners.push(TcpListener::bind(address).await?);
+    }
+    let metrics = Arc::new(Metrics::default());
+    let context = Context::new(Arc::clone(&shared_config), Arc::clone(&metrics)).await?;
+    let queue = Arc::clone(&context.queue);
     let context = Arc::new(Mutex::new(context));
 
-    log::info!("Listening on {}", socket);
+    if config.rcon.enabled {
+        let rcon = rcon::serve(
+            config.rcon.bind.clone(),
+            config.rcon.password.clone(),
+            Arc::clone(&context),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = rcon.await {
+                log::error!("RCON server stopped: {:?}", e);
+            }
+        });
+    }
+
+    if config.metrics.enabled {
+        let server = metrics::serve(config.metrics.bind.clone(), Arc::clone(&metrics));
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                log::error!("Metrics server stopped: {:?}", e);
+            }
+        });
+    }
+
+    if config.health.enabled {
+        let server = health::serve(config.health.bind.clone(), Arc::clone(&context), Arc::clone(&metrics));
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                log::error!("Health check server stopped: {:?}", e);
+            }
+        });
+    }
+
+    // One accept task per address, all feeding the same loop so the limits apply across them
+    let (accepted, mut incoming) = mpsc::channel(64);
+    for listener in listeners {
+        log::info!("Listening on {}", listener.local_addr()?);
 
-    loop {
-        let (socket, peer) = listener.accept().await?;
+        let accepted = accepted.clone();
+        tokio::spawn(async move {
+            loop {
+                let result = listener.accept().await;
+                let failed = result.is_err();
+                if accepted.send(result).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+    }
+    drop(accepted);
+
+    while let Some(result) = incoming.recv().await {
+        let (socket, peer) = result?;
+        // IPv4 clients reaching a dual-stack `[::]` listener show up as IPv4-mapped addresses
+        let peer = SocketAddr::new(peer.ip().to_canonical(), peer.port());
+
+        let (slot, ticket) = match queue.try_acquire() {
+            Some(slot) => (Some(slot), None),
+            None => match queue.enqueue() {
+                Some(ticket) => {
+                    log::debug!("Queueing connection from {}: server full, {} waiting.", peer, queue.waiting());
+                    (None, Some(ticket))
+                }
+                None => {
+                    log::warn!("Refusing connection from {}: server full.", peer);
+                    drop(socket);
+                    continue;
+                }
+            },
+        };
 
         log::debug!("Accepted connection from: {}", socket.peer_addr()?);
 
-        let state = State::new(Arc::clone(&context), peer);
+        metrics.connections_total.inc();
+        metrics.active_connections.inc();
+
+        let mut state = State::new(Arc::clone(&context), shared_config.get(), peer);
+        state.slot = slot;
+        state.queued = ticket;
+        let metrics = Arc::clone(&metrics);
         tokio::spawn(async move {
             state.connect(socket).await;
+            metrics.active_connections.dec();
         });
     }
+
+    Ok(())
 }
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Text shown to players in one language. `&` color codes work everywhere, and words in
+/// braces like `{seconds}` are filled in where noted.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Messages {
+    // Prompts
+    pub login_prompt: String,
+    pub register_prompt: String,
+    /// Used instead of `register_prompt` when `login.require_email` is set.
+    pub register_email_prompt: String,
+    pub captcha_prompt: String,
+    pub totp_prompt: String,
+    /// `{seconds}`.
+    pub login_countdown: String,
+    /// `{position}`. Shown on the action bar while waiting for a slot.
+    pub queue_position: String,
+
+    // Kicks
+    pub invalid_username: String,
+    /// `{versions}`.
+    pub unsupported_version: String,
+    pub banned: String,
+    pub invalid_password: String,
+    pub invalid_totp_code: String,
+    pub passwords_do_not_match: String,
+    pub invalid_email: String,
+    /// `{seconds}`. Followed by `retry_hint`.
+    pub registration_cooldown: String,
+    pub too_many_accounts: String,
+    pub already_registered: String,
+    pub wrong_captcha_code: String,
+    pub resource_pack_required: String,
+    pub login_timeout: String,
+    /// Followed by `retry_hint` unless `database.reconnect_after_secs` is 0.
+    pub database_error: String,
+    pub no_backend: String,
+    pub invalid_command: String,
+    /// `{usage}`. Kicks players who haven't logged in, and is only a chat message after.
+    pub invalid_syntax: String,
+    pub unexpected_packet: String,
+    pub packet_spam: String,
+    /// `{reason}`, `{seconds}`. Wraps kicks for passing problems, telling players when to
+    /// come back.
+    pub retry_hint: String,
+
+    // Chat
+    pub wait_for_slot: String,
+    pub welcome_first: String,
+    pub move_first: String,
+    pub captcha_first: String,
+    pub captcha_solved: String,
+    pub log_in_first: String,
+    pub insufficient_permissions: String,
+    pub password_changed: String,
+    pub incorrect_password: String,
+    pub sessions_cleared: String,
+    /// Kicks the player, who has to register again to play.
+    pub account_deleted: String,
+    pub account_deletion_disabled: String,
+    pub totp_unavailable: String,
+    pub totp_already_enabled: String,
+    /// `{secret}`.
+    pub totp_secret: String,
+    pub totp_confirm_prompt: String,
+    pub totp_retry: String,
+    pub totp_enabled: String,
+    pub nothing_to_verify: String,
+    /// `{action}`, `{name}`.
+    pub whitelist_updated: String,
+    pub config_reloaded: String,
+    pub config_reload_failed: String,
+    /// `{count}`, `{names}`.
+    pub player_list: String,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self {
+            login_prompt: String::from("&e/login [password]"),
+            register_prompt: String::from("&e/register [password] [password]"),
+            register_email_prompt: String::from("&e/register [password] [password] [email]"),
+            captcha_prompt: String::from("&eEnter the code on the map with /verify [code]"),
+            totp_prompt: String::from("Enter the code from your authenticator app with /2fa [code]"),
+            login_countdown: String::from("&cAuto-kick in {seconds}s"),
+            queue_position: String::from("&eThe server is full. Position in queue: &f{position}"),
+
+            invalid_username: String::from("&cInvalid username."),
+            unsupported_version: String::from("Unsupported client version. Please connect using {versions}."),
+            banned: String::from("&cYou are banned from this server."),
+            invalid_password: String::from("&cInvalid password or user not registered."),
+            invalid_totp_code: String::from("&cInvalid two-factor code."),
+            passwords_do_not_match: String::from("Passwords do not match."),
+            invalid_email: String::from("&cInvalid email address."),
+            registration_cooldown: String::from("&cYou have registered an account too recently."),
+            too_many_accounts: String::from("&cNo more accounts can be registered from your IP address."),
+            already_registered: String::from("This user is already registered."),
+            wrong_captcha_code: String::from("&cWrong code."),
+            resource_pack_required: String::from("&cThis server requires its resource pack."),
+            login_timeout: String::from("You took too long to log in."),
+            database_error: String::from("Database error. If it keeps happening, please contact one of the admins."),
+            no_backend: String::from("There is no server to send you to. Please contact one of the admins."),
+            invalid_command: String::from("Invalid command."),
+            invalid_syntax: String::from("Invalid syntax. Usage: {usage}"),
+            unexpected_packet: String::from("Unexpected packet."),
+            packet_spam: String::from("&cPacket spam."),
+            retry_hint: String::from("{reason}\n&7Please reconnect in {seconds}s."),
+
+            wait_for_slot: String::from("&ePlease wait until it's your turn to log in."),
+            welcome_first: String::from("&ePlease read the welcome message first."),
+            move_first: String::from("&ePlease move first."),
+            captcha_first: String::from("&ePlease enter the code on the map with /verify [code] first."),
+            captcha_solved: String::from("&aThanks! You can log in now."),
+            log_in_first: String::from("&cPlease log in first."),
+            insufficient_permissions: String::from("&cInsufficient permissions."),
+            password_changed: String::from("Your password has been changed."),
+            incorrect_password: String::from("&cIncorrect password."),
+            sessions_cleared: String::from("Your saved sessions have been cleared."),
+            account_deleted: String::from("Your account has been deleted."),
+            account_deletion_disabled: String::from("&cAccounts can't be deleted on this server."),
+            totp_unavailable: String::from("Two-factor authentication is not enabled on this server."),
+            totp_already_enabled: String::from("Two-factor authentication is already enabled."),
+            totp_secret: String::from("Add this secret to your authenticator app: {secret}"),
+            totp_confirm_prompt: String::from("Then confirm it with /2fa [code]"),
+            totp_retry: String::from("Invalid code, please try again."),
+            totp_enabled: String::from("Two-factor authentication enabled."),
+            nothing_to_verify: String::from("There is nothing to verify."),
+            whitelist_updated: String::from("Whitelist updated ({action} {name})."),
+            config_reloaded: String::from("&aConfig reloaded. It applies to new connections."),
+            config_reload_failed: String::from("&cThe config couldn't be loaded, see the server log."),
+            player_list: String::from("There are {count} players online: {names}"),
+        }
+    }
+}
+
+/// Messages for every locale that has a `messages.<locale>.toml` file next to `config.toml`,
+/// e.g. `messages.de.toml` or `messages.pt_br.toml`. Keys left out of a file keep the text
+/// of the default locale, whose own missing keys keep the English defaults.
+#[derive(Debug, Clone, Default)]
+pub struct Localized {
+    fallback: Messages,
+    locales: HashMap<String, Messages>,
+}
+
+impl Localized {
+    pub fn load(dir: impl AsRef<Path>, default_locale: &str) -> Result<Self> {
+        let dir = dir.as_ref();
+        let default_locale = default_locale.to_lowercase();
+
+        let default_path = dir.join(format!("messages.{default_locale}.toml"));
+        let fallback = match default_path.exists() {
+            true => toml::from_str(&std::fs::read_to_string(&default_path)?)?,
+            false => Messages::default(),
+        };
+        let base = toml::Table::try_from(&fallback)?;
+
+        let mut locales = HashMap::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Some(locale) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("messages.")?.strip_suffix(".toml"))
+                .map(str::to_lowercase)
+            else {
+                continue;
+            };
+            if locale.is_empty() || locale == default_locale {
+                continue;
+            }
+
+            let mut table = base.clone();
+            table.extend(toml::from_str::<toml::Table>(&std::fs::read_to_string(&path)?)?);
+            locales.insert(locale, toml::Value::Table(table).try_into()?);
+        }
+
+        if !locales.is_empty() {
+            log::info!("Loaded messages for {} locale(s) besides {default_locale}.", locales.len());
+        }
+        Ok(Self { fallback, locales })
+    }
+
+    /// Messages for a locale as clients report it (`de_de`), trying the exact locale, then
+    /// its language (`de`), then the default locale.
+    pub fn get(&self, locale: &str) -> &Messages {
+        let locale = locale.to_lowercase();
+        let language = locale.split('_').next().unwrap_or_default();
+        self.locales
+            .get(&locale)
+            .or_else(|| self.locales.get(language))
+            .unwrap_or(&self.fallback)
+    }
+
+    /// Messages of the default locale, for when the player's locale isn't known.
+    pub fn fallback(&self) -> &Messages {
+        &self.fallback
+    }
+}
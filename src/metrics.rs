@@ -0,0 +1,118 @@
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Largest request we read before answering; scrapers send a few hundred bytes at most.
+const MAX_REQUEST_LENGTH: usize = 8192;
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub connections_total: Counter,
+    pub active_connections: Counter,
+    pub logins_success_total: Counter,
+    pub logins_failed_total: Counter,
+    pub slow_logins_total: Counter,
+    pub db_errors_total: Counter,
+}
+
+impl Metrics {
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let metrics = [
+            ("void_connections_total", "counter", "Connections accepted.", &self.connections_total),
+            ("void_active_connections", "gauge", "Connections currently open.", &self.active_connections),
+            ("void_logins_success_total", "counter", "Players that logged in.", &self.logins_success_total),
+            ("void_logins_failed_total", "counter", "Wrong passwords and 2FA codes.", &self.logins_failed_total),
+            ("void_slow_logins_total", "counter", "Players slow to log in.", &self.slow_logins_total),
+            ("void_db_errors_total", "counter", "Database errors.", &self.db_errors_total),
+        ];
+
+        let mut out = String::new();
+        for (name, kind, help, counter) in metrics {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            let _ = writeln!(out, "{name} {}", counter.get());
+        }
+        out
+    }
+}
+
+pub async fn serve(bind: String, metrics: Arc<Metrics>) -> Result<()> {
+    let listener = TcpListener::bind(&bind).await?;
+    log::info!("Serving metrics on {}", bind);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let metrics = Arc::clone(&metrics);
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_request(stream, &metrics).await {
+                log::debug!("Metrics request from {} failed: {:?}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_request(mut stream: TcpStream, metrics: &Metrics) -> Result<()> {
+    let Some((method, path)) = read_request(&mut stream).await? else {
+        return Ok(());
+    };
+
+    let (status, body) = match (method.as_str(), path.as_str()) {
+        ("GET", "/metrics") => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", String::new()),
+    };
+    write_response(&mut stream, status, "text/plain; version=0.0.4", &body).await
+}
+
+/// Reads the head of an HTTP request and returns its method and path, or `None` if the
+/// client hangs up or sends too much first.
+pub async fn read_request(stream: &mut TcpStream) -> Result<Option<(String, String)>> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || request.len() + read > MAX_REQUEST_LENGTH {
+            return Ok(None);
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut words = request.split_whitespace();
+    let method = words.next().unwrap_or_default().to_string();
+    let path = words.next().unwrap_or_default().to_string();
+    Ok(Some((method, path)))
+}
+
+/// Sends a complete response and closes the connection.
+pub async fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
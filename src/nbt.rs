@@ -1,6 +1,26 @@
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use json::number::Number;
 use json::JsonValue;
 
-#[derive(Debug)]
+/// How deeply compounds and lists may nest before conversion gives up. Matches vanilla's
+/// limit and keeps untrusted input from overflowing the stack.
+pub const MAX_NBT_DEPTH: usize = 512;
+
+fn check_depth(depth: usize) -> Result<()> {
+    if depth > MAX_NBT_DEPTH {
+        return Err(anyhow!("NBT nested deeper than {MAX_NBT_DEPTH} levels"));
+    }
+    Ok(())
+}
+
+/// Tags compare structurally, and like `f32`/`f64` a `Float` or `Double` holding NaN is
+/// never equal to anything, itself included. Compounds compare in order.
+#[derive(Debug, Clone, PartialEq)]
 pub enum NBT {
     End,
     Byte(i8),
@@ -17,13 +37,38 @@ pub enum NBT {
     LongArray(Vec<i64>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct NamedTag {
     pub tag: NBT,
     pub name: String,
 }
 
 impl NBT {
+    /// The value of a compound's field. `None` if this isn't a compound or has no such field.
+    pub fn get(&self, key: &str) -> Option<&NBT> {
+        match self {
+            NBT::Compound(tags) => tags.iter().find(|tag| tag.name == key).map(|tag| &tag.tag),
+            _ => None,
+        }
+    }
+
+    /// An element of a list. `None` if this isn't a list or the index is out of range.
+    pub fn index(&self, index: usize) -> Option<&NBT> {
+        match self {
+            NBT::List(tags) => tags.get(index),
+            _ => None,
+        }
+    }
+
+    /// Follows a path of compound keys and list indices, e.g.
+    /// `codec.get_path(&["minecraft:dimension_type", "value", "0", "element", "height"])`.
+    pub fn get_path(&self, path: &[&str]) -> Option<&NBT> {
+        path.iter().try_fold(self, |tag, segment| match tag {
+            NBT::List(_) => tag.index(segment.parse().ok()?),
+            _ => tag.get(segment),
+        })
+    }
+
     pub fn type_id(&self) -> u8 {
         match self {
             NBT::End => 0,
@@ -42,79 +87,180 @@ impl NBT {
         }
     }
 
+    /// Checks that every list, however deeply nested, holds a single tag type.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            NBT::List(vec) => {
+                let type_id = vec.first().map(NBT::type_id).unwrap_or(0);
+                if let Some(other) = vec.iter().find(|tag| tag.type_id() != type_id) {
+                    return Err(anyhow!(
+                        "NBT list mixes tag types {} and {}",
+                        type_id,
+                        other.type_id()
+                    ));
+                }
+                vec.iter().try_for_each(NBT::validate)
+            }
+            NBT::Compound(vec) => vec.iter().try_for_each(|tag| tag.tag.validate()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Like [`NBT::to_bytes`], but fails on lists that mix tag types instead of writing
+    /// them as the type of their first element.
+    pub fn to_bytes_checked(&self) -> Result<Vec<u8>> {
+        self.validate()?;
+        Ok(self.to_bytes())
+    }
+
+    /// Lists are written as the type of their first element, so a list mixing types comes
+    /// out unreadable. Use [`NBT::to_bytes_checked`] for tags that might hold one.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut out = vec![];
         match &self {
             NBT::End => {
-                return vec![0x0];
+                out.push(0x0);
             }
             NBT::Byte(b) => {
-                return out;
+                out.push(*b as u8);
             }
             NBT::Short(s) => {
                 out.extend_from_slice(&s.to_be_bytes());
-                return out;
             }
             NBT::Int(i) => {
                 out.extend_from_slice(&i.to_be_bytes());
-                return out;
             }
             NBT::Long(l) => {
                 out.extend_from_slice(&l.to_be_bytes());
-                return out;
             }
             NBT::Float(f) => {
                 out.extend_from_slice(&f.to_be_bytes());
-                return out;
             }
             NBT::Double(d) => {
                 out.extend_from_slice(&d.to_be_bytes());
-                return out;
             }
             NBT::ByteArray(vec) => {
-                out.extend_from_slice(&(vec.len() as u16).to_be_bytes());
-                out.extend_from_slice(&vec);
-                return out;
+                out.extend_from_slice(&(vec.len() as i32).to_be_bytes());
+                out.extend_from_slice(vec);
             }
             NBT::String(s) => {
-                out.extend_from_slice(&(s.as_bytes().len() as u16).to_be_bytes());
-                out.extend_from_slice(&s.as_bytes());
-                return out;
+                out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+                out.extend_from_slice(s.as_bytes());
             }
             NBT::List(vec) => {
                 let type_id = vec.first().map(|t| t.type_id()).unwrap_or(0);
                 out.push(type_id);
                 out.extend_from_slice(&(vec.len() as i32).to_be_bytes());
                 for nbt in vec {
-                    assert!(nbt.type_id() == type_id);
                     out.extend_from_slice(&nbt.to_bytes());
                 }
-                return out;
             }
             NBT::Compound(vec) => {
                 for tag in vec {
                     out.extend_from_slice(&tag.to_bytes());
                 }
                 out.push(0x0);
-                return out;
             }
             NBT::IntArray(vec) => {
                 out.extend_from_slice(&(vec.len() as i32).to_be_bytes());
                 for i in vec {
                     out.extend_from_slice(&i.to_be_bytes());
                 }
-                // out.push(0x0);
-                return out;
             }
             NBT::LongArray(vec) => {
                 out.extend_from_slice(&(vec.len() as i32).to_be_bytes());
                 for l in vec {
                     out.extend_from_slice(&l.to_be_bytes());
                 }
-                // out.push(0x0);
-                return out;
             }
         }
+        out
+    }
+}
+
+fn read_array<const N: usize>(reader: &mut (impl Read + ?Sized)) -> Result<[u8; N]> {
+    let mut buffer = [0u8; N];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn read_length(reader: &mut (impl Read + ?Sized)) -> Result<usize> {
+    let length = i32::from_be_bytes(read_array(reader)?);
+    usize::try_from(length).map_err(|_| anyhow!("Negative NBT length {length}"))
+}
+
+fn read_string(reader: &mut (impl Read + ?Sized)) -> Result<String> {
+    let length = u16::from_be_bytes(read_array(reader)?) as usize;
+    let mut buffer = vec![0u8; length];
+    reader.read_exact(&mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+/// Reads `length` elements without trusting `length` for the allocation up front.
+fn read_elements<T>(
+    reader: &mut dyn Read,
+    length: usize,
+    mut read: impl FnMut(&mut dyn Read) -> Result<T>,
+) -> Result<Vec<T>> {
+    let mut out = Vec::with_capacity(length.min(4096));
+    for _ in 0..length {
+        out.push(read(reader)?);
+    }
+    Ok(out)
+}
+
+impl NBT {
+    /// Reads the payload of a tag whose type has already been read.
+    pub fn read(reader: &mut dyn Read, type_id: u8, depth: usize) -> Result<NBT> {
+        let tag = match type_id {
+            0 => NBT::End,
+            1 => NBT::Byte(i8::from_be_bytes(read_array(reader)?)),
+            2 => NBT::Short(i16::from_be_bytes(read_array(reader)?)),
+            3 => NBT::Int(i32::from_be_bytes(read_array(reader)?)),
+            4 => NBT::Long(i64::from_be_bytes(read_array(reader)?)),
+            5 => NBT::Float(f32::from_be_bytes(read_array(reader)?)),
+            6 => NBT::Double(f64::from_be_bytes(read_array(reader)?)),
+            7 => {
+                let length = read_length(reader)?;
+                let mut out = vec![];
+                (&mut *reader).take(length as u64).read_to_end(&mut out)?;
+                if out.len() != length {
+                    return Err(anyhow!("NBT byte array ended early"));
+                }
+                NBT::ByteArray(out)
+            }
+            8 => NBT::String(read_string(reader)?),
+            9 => {
+                check_depth(depth)?;
+                let element_type = read_array::<1>(reader)?[0];
+                let length = read_length(reader)?;
+                if element_type == 0 && length > 0 {
+                    return Err(anyhow!("NBT list of End tags must be empty"));
+                }
+                NBT::List(read_elements(reader, length, |r| NBT::read(r, element_type, depth + 1))?)
+            }
+            10 => {
+                check_depth(depth)?;
+                let mut tags = vec![];
+                loop {
+                    let tag = NamedTag::read_nested(reader, depth + 1)?;
+                    if tag.tag.type_id() == 0 {
+                        break NBT::Compound(tags);
+                    }
+                    tags.push(tag);
+                }
+            }
+            11 => {
+                let length = read_length(reader)?;
+                NBT::IntArray(read_elements(reader, length, |r| Ok(i32::from_be_bytes(read_array(r)?)))?)
+            }
+            12 => {
+                let length = read_length(reader)?;
+                NBT::LongArray(read_elements(reader, length, |r| Ok(i64::from_be_bytes(read_array(r)?)))?)
+            }
+            _ => return Err(anyhow!("Unknown NBT tag type {type_id}")),
+        };
+        Ok(tag)
     }
 }
 
@@ -132,60 +278,564 @@ impl NamedTag {
         }
 
         let mut out = vec![self.tag.type_id()];
-        out.extend_from_slice(&(self.name.as_bytes().len() as u16).to_be_bytes());
-        out.extend_from_slice(&self.name.as_bytes());
+        out.extend_from_slice(&(self.name.len() as u16).to_be_bytes());
+        out.extend_from_slice(self.name.as_bytes());
         out.extend_from_slice(&self.tag.to_bytes());
 
         out
     }
+
+    pub fn to_bytes_checked(&self) -> Result<Vec<u8>> {
+        self.tag.validate()?;
+        Ok(self.to_bytes())
+    }
+
+    /// Reads an uncompressed named tag, as found at the root of an NBT file.
+    pub fn read(reader: &mut impl Read) -> Result<NamedTag> {
+        Self::read_nested(reader, 1)
+    }
+
+    fn read_nested(reader: &mut dyn Read, depth: usize) -> Result<NamedTag> {
+        let type_id = read_array::<1>(reader)?[0];
+        if type_id == 0 {
+            return Ok(NamedTag::new("", NBT::End));
+        }
+
+        let name = read_string(reader)?;
+        let tag = NBT::read(reader, type_id, depth)?;
+        Ok(NamedTag::new(name, tag))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zlib,
+}
+
+/// Reads an NBT file such as `level.dat`, detecting whether it is gzip, zlib or not
+/// compressed at all.
+pub fn read_file(path: impl AsRef<Path>) -> Result<NamedTag> {
+    let mut data = vec![];
+    std::fs::File::open(path)?.read_to_end(&mut data)?;
+
+    let mut reader: Box<dyn Read> = match data.as_slice() {
+        [0x1f, 0x8b, ..] => Box::new(BufReader::new(GzDecoder::new(data.as_slice()))),
+        // zlib header: deflate method, and the first two bytes form a multiple of 31
+        [cmf, flg, ..] if cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0 => {
+            Box::new(BufReader::new(ZlibDecoder::new(data.as_slice())))
+        }
+        _ => Box::new(data.as_slice()),
+    };
+    NamedTag::read(&mut reader)
 }
 
-fn from_json_object(data: json::object::Object) -> NBT {
+pub fn write_file(path: impl AsRef<Path>, tag: &NamedTag, compression: Compression) -> Result<()> {
+    let bytes = tag.to_bytes_checked()?;
+    let data = match compression {
+        Compression::None => bytes,
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+            encoder.write_all(&bytes)?;
+            encoder.finish()?
+        }
+        Compression::Zlib => {
+            let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::default());
+            encoder.write_all(&bytes)?;
+            encoder.finish()?
+        }
+    };
+    std::fs::write(path, data)?;
+    Ok(())
+}
+
+/// Fluent construction of compound tags, e.g.
+/// `CompoundBuilder::new().int("a", 1).compound("b", |b| b.string("c", "d")).build()`.
+#[derive(Debug, Default)]
+pub struct CompoundBuilder {
+    tags: Vec<NamedTag>,
+}
+
+impl CompoundBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tag(mut self, name: impl Into<String>, tag: NBT) -> Self {
+        self.tags.push(NamedTag::new(name, tag));
+        self
+    }
+
+    pub fn byte(self, name: impl Into<String>, value: i8) -> Self {
+        self.tag(name, NBT::Byte(value))
+    }
+
+    pub fn bool(self, name: impl Into<String>, value: bool) -> Self {
+        self.tag(name, NBT::Byte(value as i8))
+    }
+
+    pub fn short(self, name: impl Into<String>, value: i16) -> Self {
+        self.tag(name, NBT::Short(value))
+    }
+
+    pub fn int(self, name: impl Into<String>, value: i32) -> Self {
+        self.tag(name, NBT::Int(value))
+    }
+
+    pub fn long(self, name: impl Into<String>, value: i64) -> Self {
+        self.tag(name, NBT::Long(value))
+    }
+
+    pub fn float(self, name: impl Into<String>, value: f32) -> Self {
+        self.tag(name, NBT::Float(value))
+    }
+
+    pub fn double(self, name: impl Into<String>, value: f64) -> Self {
+        self.tag(name, NBT::Double(value))
+    }
+
+    pub fn string(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tag(name, NBT::String(value.into()))
+    }
+
+    pub fn byte_array(self, name: impl Into<String>, value: Vec<u8>) -> Self {
+        self.tag(name, NBT::ByteArray(value))
+    }
+
+    pub fn int_array(self, name: impl Into<String>, value: Vec<i32>) -> Self {
+        self.tag(name, NBT::IntArray(value))
+    }
+
+    pub fn long_array(self, name: impl Into<String>, value: Vec<i64>) -> Self {
+        self.tag(name, NBT::LongArray(value))
+    }
+
+    pub fn list(self, name: impl Into<String>, value: Vec<NBT>) -> Self {
+        self.tag(name, NBT::List(value))
+    }
+
+    pub fn compound(
+        self,
+        name: impl Into<String>,
+        build: impl FnOnce(CompoundBuilder) -> CompoundBuilder,
+    ) -> Self {
+        self.tag(name, build(CompoundBuilder::new()).into_nbt())
+    }
+
+    pub fn into_nbt(self) -> NBT {
+        NBT::Compound(self.tags)
+    }
+
+    /// Finishes the compound as an unnamed root tag, as sent over the network.
+    pub fn build(self) -> NamedTag {
+        NamedTag::new("", self.into_nbt())
+    }
+}
+
+/// Picks the narrowest tag that holds `number` without loss: `Int`, then `Long` for whole
+/// numbers; `Float` if the value survives a round trip through `f32`, otherwise `Double`.
+fn from_json_number(number: Number) -> NBT {
+    let (positive, mantissa, exponent) = number.as_parts();
+    let whole = if exponent >= 0 {
+        10u64
+            .checked_pow(exponent as u32)
+            .and_then(|power| mantissa.checked_mul(power))
+    } else {
+        10u64
+            .checked_pow(exponent.unsigned_abs() as u32)
+            .filter(|power| mantissa % power == 0)
+            .map(|power| mantissa / power)
+    };
+
+    if let Some(whole) = whole.and_then(|w| i64::try_from(w).ok()) {
+        let whole = if positive { whole } else { -whole };
+        return match i32::try_from(whole) {
+            Ok(int) => NBT::Int(int),
+            Err(_) => NBT::Long(whole),
+        };
+    }
+
+    let double = f64::from(number);
+    let float = double as f32;
+    if float.to_string().parse::<f64>() == Ok(double) {
+        NBT::Float(float)
+    } else {
+        NBT::Double(double)
+    }
+}
+
+/// Promotes the numeric elements of a JSON array to a single tag type, since NBT lists
+/// must be homogeneous (e.g. `[1, 2.5]` becomes two floats rather than an int and a float).
+fn widen_numbers(list: Vec<NBT>) -> Vec<NBT> {
+    let rank = |tag: &NBT| match tag {
+        NBT::Int(_) => Some(0),
+        NBT::Long(_) => Some(1),
+        NBT::Float(_) => Some(2),
+        NBT::Double(_) => Some(3),
+        _ => None,
+    };
+
+    let Some(ranks) = list.iter().map(rank).collect::<Option<Vec<_>>>() else {
+        return list;
+    };
+    let (Some(&lowest), Some(&highest)) = (ranks.iter().min(), ranks.iter().max()) else {
+        return list;
+    };
+    if lowest == highest {
+        return list;
+    }
+    // A float can't hold every long, so mixing the two needs a double.
+    let target = if highest == 2 && ranks.contains(&1) { 3 } else { highest };
+
+    list.into_iter()
+        .map(|tag| match (target, tag) {
+            (1, NBT::Int(i)) => NBT::Long(i as i64),
+            (2, NBT::Int(i)) => NBT::Float(i as f32),
+            (3, NBT::Int(i)) => NBT::Double(i as f64),
+            (3, NBT::Long(l)) => NBT::Double(l as f64),
+            // Go through the shortest decimal form so 0.8f32 widens to 0.8 rather than 0.800000011920929.
+            (3, NBT::Float(f)) => NBT::Double(f.to_string().parse().unwrap_or(f as f64)),
+            (_, tag) => tag,
+        })
+        .collect()
+}
+
+fn from_json_object(data: json::object::Object, depth: usize) -> Result<NBT> {
+    check_depth(depth)?;
+    // `Object` iterates in insertion order and can't hold a key twice, so the compound
+    // comes out in document order without duplicate names.
     let mut list = vec![];
     for (k, v) in data.iter() {
         let n = match v {
-            JsonValue::Null => unimplemented!(),
+            // NBT has no null tag, so null fields are left out of the compound.
+            JsonValue::Null => {
+                log::debug!("Skipping null JSON field \"{k}\" while converting to NBT");
+                continue;
+            }
             JsonValue::Short(short) => NBT::String(short.as_str().to_string()),
             JsonValue::String(s) => NBT::String(s.to_string()),
-            JsonValue::Number(number) => {
-                let f = f64::from(number.clone());
-                if f.fract() == 0.0 {
-                    NBT::Int(f as i32)
-                } else {
-                    NBT::Float(f as f32)
-                }
-            }
+            JsonValue::Number(number) => from_json_number(*number),
             JsonValue::Boolean(b) => NBT::Byte(*b as i8),
-            JsonValue::Object(object) => from_json_object(object.clone()),
-            JsonValue::Array(vec) => from_json_array(vec.clone()),
+            JsonValue::Object(object) => from_json_object(object.clone(), depth + 1)?,
+            JsonValue::Array(vec) => from_json_array(vec.clone(), depth + 1)?,
         };
         list.push(NamedTag::new(k.to_string(), n));
     }
-    NBT::Compound(list)
+    Ok(NBT::Compound(list))
 }
 
-fn from_json_array(data: Vec<JsonValue>) -> NBT {
+fn from_json_array(data: Vec<JsonValue>, depth: usize) -> Result<NBT> {
+    check_depth(depth)?;
     let mut list = vec![];
     for v in data {
         let n = match v {
-            JsonValue::Null => unimplemented!(),
+            JsonValue::Null => {
+                log::debug!("Dropping null JSON array element while converting to NBT");
+                continue;
+            }
             JsonValue::Short(short) => NBT::String(short.as_str().to_string()),
             JsonValue::String(s) => NBT::String(s),
-            JsonValue::Number(number) => NBT::Int(number.as_fixed_point_i64(0).unwrap() as i32),
+            JsonValue::Number(number) => from_json_number(number),
             JsonValue::Boolean(b) => NBT::Byte(b as i8),
-            JsonValue::Object(object) => from_json_object(object),
-            JsonValue::Array(vec) => from_json_array(vec),
+            JsonValue::Object(object) => from_json_object(object, depth + 1)?,
+            JsonValue::Array(vec) => from_json_array(vec, depth + 1)?,
         };
         list.push(n);
     }
-    NBT::List(list)
+    Ok(NBT::List(wrap_mixed(widen_numbers(list))))
+}
+
+/// Lists that still mix tag types once numbers are widened (e.g. `["a", {"text": "b"}]`
+/// in a chat component) get every element wrapped in a compound with a single unnamed
+/// field, like vanilla does. Compounds are left as they are, unless they'd read back as
+/// wrapped themselves.
+fn wrap_mixed(list: Vec<NBT>) -> Vec<NBT> {
+    let Some(type_id) = list.first().map(NBT::type_id) else {
+        return list;
+    };
+    if list.iter().all(|tag| tag.type_id() == type_id) {
+        return list;
+    }
+
+    list.into_iter()
+        .map(|tag| match tag {
+            NBT::Compound(tags) if !(tags.len() == 1 && tags[0].name.is_empty()) => NBT::Compound(tags),
+            tag => NBT::Compound(vec![NamedTag::new("", tag)]),
+        })
+        .collect()
+}
+
+/// Converts a JSON object to an unnamed root compound. Fields keep the order they have in
+/// `s`, which matters for the registry codec. If a key appears more than once in an object,
+/// the last value is kept, at the position of the first occurrence.
+pub fn from_json(s: &str) -> Result<NamedTag> {
+    match json::parse(s)? {
+        JsonValue::Object(o) => Ok(NamedTag::new("", from_json_object(o, 1)?)),
+        _ => Err(anyhow!("JSON root must be an object to convert to NBT")),
+    }
+}
+
+struct SnbtParser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
 }
 
-pub fn from_json(s: &str) -> NamedTag {
-    let data = json::parse(s).unwrap();
+impl<'a> SnbtParser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            pos: 0,
+            depth: 0,
+        }
+    }
 
-    match data {
-        JsonValue::Object(o) => NamedTag::new("", from_json_object(o)),
-        _ => unimplemented!(),
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.bump() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(anyhow!(
+                "SNBT: expected '{expected}' but found '{c}' at offset {}",
+                self.pos - c.len_utf8()
+            )),
+            None => Err(anyhow!("SNBT: expected '{expected}' but reached end of input")),
+        }
+    }
+
+    fn is_unquoted_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')
+    }
+
+    fn read_unquoted(&mut self) -> Result<&'a str> {
+        let start = self.pos;
+        while self.peek().is_some_and(Self::is_unquoted_char) {
+            self.bump();
+        }
+        if start == self.pos {
+            return match self.peek() {
+                Some(c) => Err(anyhow!("SNBT: unexpected '{c}' at offset {start}")),
+                None => Err(anyhow!("SNBT: unexpected end of input")),
+            };
+        }
+        Ok(&self.input[start..self.pos])
+    }
+
+    fn read_quoted(&mut self) -> Result<String> {
+        let quote = self.bump().ok_or_else(|| anyhow!("SNBT: unexpected end of input"))?;
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                Some('\\') => match self.bump() {
+                    Some(c) => out.push(c),
+                    None => return Err(anyhow!("SNBT: unterminated escape sequence")),
+                },
+                Some(c) if c == quote => return Ok(out),
+                Some(c) => out.push(c),
+                None => return Err(anyhow!("SNBT: unterminated string")),
+            }
+        }
     }
+
+    fn read_key(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"') | Some('\'') => self.read_quoted(),
+            _ => Ok(self.read_unquoted()?.to_string()),
+        }
+    }
+
+    fn read_value(&mut self) -> Result<NBT> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.read_nested(Self::read_compound),
+            Some('[') => self.read_nested(Self::read_list_or_array),
+            Some('"') | Some('\'') => Ok(NBT::String(self.read_quoted()?)),
+            _ => {
+                let token = self.read_unquoted()?;
+                Ok(parse_scalar(token))
+            }
+        }
+    }
+
+    fn read_nested(&mut self, read: fn(&mut Self) -> Result<NBT>) -> Result<NBT> {
+        self.depth += 1;
+        check_depth(self.depth)?;
+        let value = read(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn read_compound(&mut self) -> Result<NBT> {
+        self.expect('{')?;
+        let mut tags = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(NBT::Compound(tags));
+        }
+        loop {
+            let key = self.read_key()?;
+            self.expect(':')?;
+            let value = self.read_value()?;
+            tags.push(NamedTag::new(key, value));
+
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => continue,
+                Some('}') => return Ok(NBT::Compound(tags)),
+                Some(c) => return Err(anyhow!("SNBT: expected ',' or '}}' but found '{c}'")),
+                None => return Err(anyhow!("SNBT: unterminated compound")),
+            }
+        }
+    }
+
+    fn read_list_or_array(&mut self) -> Result<NBT> {
+        self.expect('[')?;
+        let rest = &self.input[self.pos..];
+        let mut prefix = rest.chars();
+        if let (Some(kind @ ('B' | 'I' | 'L')), Some(';')) = (prefix.next(), prefix.next()) {
+            self.pos += 2;
+            return self.read_typed_array(kind);
+        }
+
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.bump();
+            return Ok(NBT::List(values));
+        }
+        loop {
+            let value = self.read_value()?;
+            if let Some(first) = values.first() {
+                if first.type_id() != value.type_id() {
+                    return Err(anyhow!("SNBT: list elements must all be of the same type"));
+                }
+            }
+            values.push(value);
+
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => continue,
+                Some(']') => return Ok(NBT::List(values)),
+                Some(c) => return Err(anyhow!("SNBT: expected ',' or ']' but found '{c}'")),
+                None => return Err(anyhow!("SNBT: unterminated list")),
+            }
+        }
+    }
+
+    fn read_typed_array(&mut self, kind: char) -> Result<NBT> {
+        let mut tokens = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.bump();
+        } else {
+            loop {
+                self.skip_whitespace();
+                tokens.push(self.read_unquoted()?);
+                self.skip_whitespace();
+                match self.bump() {
+                    Some(',') => continue,
+                    Some(']') => break,
+                    Some(c) => return Err(anyhow!("SNBT: expected ',' or ']' but found '{c}'")),
+                    None => return Err(anyhow!("SNBT: unterminated array")),
+                }
+            }
+        }
+
+        let invalid = |token: &str| anyhow!("SNBT: invalid element '{token}' in [{kind};] array");
+        match kind {
+            'B' => tokens
+                .into_iter()
+                .map(|t| {
+                    let digits = t.strip_suffix(['b', 'B']).unwrap_or(t);
+                    digits.parse::<i8>().map(|b| b as u8).map_err(|_| invalid(t))
+                })
+                .collect::<Result<Vec<_>>>()
+                .map(NBT::ByteArray),
+            'I' => tokens
+                .into_iter()
+                .map(|t| t.parse::<i32>().map_err(|_| invalid(t)))
+                .collect::<Result<Vec<_>>>()
+                .map(NBT::IntArray),
+            _ => tokens
+                .into_iter()
+                .map(|t| {
+                    let digits = t.strip_suffix(['l', 'L']).unwrap_or(t);
+                    digits.parse::<i64>().map_err(|_| invalid(t))
+                })
+                .collect::<Result<Vec<_>>>()
+                .map(NBT::LongArray),
+        }
+    }
+}
+
+fn parse_number(token: &str) -> Option<NBT> {
+    if !token.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '-' | '+' | '.')) {
+        return None;
+    }
+
+    let (digits, suffix) = match token.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() && !matches!(c, 'e' | 'E') => {
+            (&token[..i], Some(c.to_ascii_lowercase()))
+        }
+        _ => (token, None),
+    };
+
+    match suffix {
+        Some('b') => digits.parse().ok().map(NBT::Byte),
+        Some('s') => digits.parse().ok().map(NBT::Short),
+        Some('l') => digits.parse().ok().map(NBT::Long),
+        Some('f') => digits.parse().ok().map(NBT::Float),
+        Some('d') => digits.parse().ok().map(NBT::Double),
+        Some(_) => None,
+        None => digits
+            .parse()
+            .ok()
+            .map(NBT::Int)
+            .or_else(|| digits.parse().ok().map(NBT::Double)),
+    }
+}
+
+fn parse_scalar(token: &str) -> NBT {
+    match token {
+        "true" => NBT::Byte(1),
+        "false" => NBT::Byte(0),
+        _ => parse_number(token).unwrap_or_else(|| NBT::String(token.to_string())),
+    }
+}
+
+/// Parses Minecraft's stringified NBT (e.g. `{key:1b, list:[1,2,3], name:"x"}`),
+/// keeping the exact tag types given by suffixes and typed array prefixes.
+pub fn from_snbt(s: &str) -> Result<NamedTag> {
+    let mut parser = SnbtParser::new(s);
+    parser.skip_whitespace();
+    if parser.peek() != Some('{') {
+        return Err(anyhow!("SNBT: root tag must be a compound"));
+    }
+
+    let root = parser.read_nested(SnbtParser::read_compound)?;
+
+    parser.skip_whitespace();
+    if parser.pos != s.len() {
+        return Err(anyhow!("SNBT: trailing data at offset {}", parser.pos));
+    }
+
+    Ok(NamedTag::new("", root))
 }
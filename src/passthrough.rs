@@ -0,0 +1,75 @@
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use anyhow::Result;
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+use crate::config::PingPassthroughConfig;
+use crate::protocol::{self, packet::PacketBuilder};
+
+/// The last status fetched from the backend, or `None` if fetching it failed, so a backend
+/// that is down isn't retried on every ping either.
+#[derive(Default)]
+pub struct StatusCache {
+    last: Mutex<Option<(Instant, Option<String>)>>,
+}
+
+impl StatusCache {
+    /// The backend's status JSON, fetched at most once per `cache_secs`. Pings arriving
+    /// during a fetch wait for it rather than starting their own.
+    pub async fn get(&self, config: &PingPassthroughConfig, protocol_version: i32) -> Option<String> {
+        let mut last = self.last.lock().await;
+        let max_age = Duration::from_secs(config.cache_secs);
+        if let Some((fetched, status)) = last.as_ref() {
+            if fetched.elapsed() < max_age {
+                return status.clone();
+            }
+        }
+
+        let timeout = Duration::from_millis(config.timeout_ms);
+        let status = match tokio::time::timeout(timeout, fetch_status(&config.address, protocol_version)).await {
+            Ok(Ok(status)) => Some(status),
+            Ok(Err(e)) => {
+                log::warn!("Failed to get the status of {}: {:?}", config.address, e);
+                None
+            }
+            Err(_) => {
+                log::warn!("Failed to get the status of {}: no answer within {:?}", config.address, timeout);
+                None
+            }
+        };
+
+        *last = Some((Instant::now(), status.clone()));
+        status
+    }
+}
+
+/// Asks the server at `address` for its status the way a client would and returns the JSON
+/// it answers with.
+pub async fn fetch_status(address: &str, protocol_version: i32) -> Result<String> {
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host.trim_start_matches('[').trim_end_matches(']'), port.parse::<u16>()?),
+        None => (address, 25565),
+    };
+    let mut stream = TcpStream::connect((host, port)).await?;
+
+    let handshake = PacketBuilder::new(0x00)
+        .with_var_int(protocol_version)
+        .with_string(host)
+        .with_u16(port)
+        .with_var_int(1); // next state: status
+    protocol::send_packet(&mut stream, handshake).await?;
+    protocol::send_packet(&mut stream, PacketBuilder::new(0x00)).await?;
+
+    let (packet_id, buffer) = protocol::read_generic_packet(&mut stream).await?;
+    if packet_id != 0x00 {
+        return Err(anyhow!("Expected a status response, got packet {packet_id:#04x}"));
+    }
+    let status = protocol::read_string(&mut Cursor::new(buffer)).await?;
+
+    // Rather fall back to our own status than relay something clients can't show
+    json::parse(&status)?;
+    Ok(status)
+}
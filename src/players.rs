@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::watch;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Players in the play state, by username.
+pub type PlayerRegistry = Arc<Mutex<HashMap<String, ConnectionHandle>>>;
+
+/// Something another task wants a connection to do.
+#[derive(Debug)]
+pub enum Outbound {
+    /// A system chat message, which may use `&` color codes.
+    Message(String),
+    /// Kick the player with this reason, which may use `&` color codes.
+    Disconnect(String),
+}
+
+/// Lets other tasks message or disconnect a connection they don't own.
+#[derive(Debug, Clone)]
+pub struct ConnectionHandle {
+    sender: UnboundedSender<Outbound>,
+    /// Set to the player's UUID once they have logged in.
+    authenticated: Arc<OnceLock<Uuid>>,
+    /// Flipped to true to close the connection, even in the middle of reading a packet.
+    cancel: Arc<watch::Sender<bool>>,
+}
+
+impl ConnectionHandle {
+    pub fn new() -> (Self, UnboundedReceiver<Outbound>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let handle = Self {
+            sender,
+            authenticated: Arc::default(),
+            cancel: Arc::new(watch::Sender::new(false)),
+        };
+        (handle, receiver)
+    }
+
+    /// Queues a chat message for the connection, which builds the packet for its own
+    /// protocol version. Returns false if it has already closed.
+    pub fn send_message(&self, text: &str) -> bool {
+        self.sender.send(Outbound::Message(text.to_string())).is_ok()
+    }
+
+    /// Kicks a player in the play state. The reason may use `&` color codes.
+    pub fn disconnect(&self, reason: &str) -> bool {
+        let sent = self.sender.send(Outbound::Disconnect(reason.to_string())).is_ok();
+        self.cancel();
+        sent
+    }
+
+    /// Closes the connection once the packets queued so far are written. A packet the
+    /// connection is partway through reading is abandoned.
+    pub fn cancel(&self) {
+        self.cancel.send_replace(true);
+    }
+
+    pub fn cancelled(&self) -> Cancellation {
+        Cancellation(self.cancel.subscribe())
+    }
+
+    pub fn same_connection(&self, other: &ConnectionHandle) -> bool {
+        self.sender.same_channel(&other.sender)
+    }
+
+    pub fn set_authenticated(&self, uuid: Uuid) {
+        let _ = self.authenticated.set(uuid);
+    }
+
+    pub fn authenticated_uuid(&self) -> Option<Uuid> {
+        self.authenticated.get().copied()
+    }
+}
+
+/// Tells a connection it has been cancelled through its [`ConnectionHandle`].
+#[derive(Debug)]
+pub struct Cancellation(watch::Receiver<bool>);
+
+impl Cancellation {
+    /// Resolves once the connection is cancelled, right away if it already is. Safe to
+    /// drop and call again, e.g. as a `select!` branch.
+    pub async fn wait(&mut self) {
+        let _ = self.0.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+/// Vanilla's rule for player names: 1 to 16 ASCII letters, digits and underscores.
+pub fn valid_username(name: &str) -> bool {
+    (1..=16).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Names and UUIDs of the players that have logged in, sorted by name.
+pub async fn online(players: &PlayerRegistry) -> Vec<(String, Uuid)> {
+    let mut online = players
+        .lock()
+        .await
+        .iter()
+        .filter_map(|(name, handle)| Some((name.clone(), handle.authenticated_uuid()?)))
+        .collect::<Vec<_>>();
+    online.sort();
+    online
+}
+
+/// Sends a chat message to every connection in the registry, logged in or not. Returns how
+/// many it was queued for.
+pub async fn broadcast(players: &PlayerRegistry, text: &str) -> usize {
+    let players = players.lock().await;
+    players.values().filter(|player| player.send_message(text)).count()
+}
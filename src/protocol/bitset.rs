@@ -0,0 +1,31 @@
+/// Java `BitSet` as the protocol sends it: a VarInt-prefixed array of longs, with bit `i`
+/// stored in long `i / 64`. Used for the light masks in chunk and light packets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitSet {
+    longs: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets bit `index`, growing the set as needed.
+    pub fn set(&mut self, index: usize) {
+        let long = index / 64;
+        if long >= self.longs.len() {
+            self.longs.resize(long + 1, 0);
+        }
+        self.longs[long] |= 1 << (index % 64);
+    }
+
+    pub fn get(&self, index: usize) -> bool {
+        self.longs
+            .get(index / 64)
+            .is_some_and(|long| long & (1 << (index % 64)) != 0)
+    }
+
+    pub fn as_longs(&self) -> &[u64] {
+        &self.longs
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::{Cursor, Read};
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+use super::packet::PacketBuilder;
+use super::play::PlayIds;
+
+/// 1.13, since which plugin channel names need a namespace.
+const NAMESPACED_CHANNEL_MIN_PROTOCOL: i32 = 393;
+
+/// Payload of a plugin message on the BungeeCord channel, which both BungeeCord and
+/// Velocity understand. Fields are encoded like Java's `DataOutputStream::writeUTF`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BungeeMessage {
+    data: Vec<u8>,
+}
+
+impl BungeeMessage {
+    pub const CHANNEL: &'static str = "bungeecord:main";
+    /// Channel name for clients older than 1.13.
+    pub const LEGACY_CHANNEL: &'static str = "BungeeCord";
+
+    /// The channel the proxy listens on for a client of `protocol_version`. BungeeCord
+    /// ignores the legacy name from newer clients.
+    pub fn channel(protocol_version: i32) -> &'static str {
+        if protocol_version >= NAMESPACED_CHANNEL_MIN_PROTOCOL {
+            Self::CHANNEL
+        } else {
+            Self::LEGACY_CHANNEL
+        }
+    }
+
+    /// Whether a plugin message on `channel` is meant for the proxy.
+    pub fn is_channel(channel: &str) -> bool {
+        channel == Self::CHANNEL || channel == Self::LEGACY_CHANNEL
+    }
+
+    pub fn new(subchannel: &str) -> Result<Self> {
+        Self { data: Vec::new() }.with_utf(subchannel)
+    }
+
+    /// Asks the proxy to move the player to `server`.
+    pub fn connect(server: &str) -> Result<Self> {
+        Self::new("Connect")?.with_utf(server)
+    }
+
+    /// Answers GetServer with the name of the server the player is on.
+    pub fn get_server(server: &str) -> Result<Self> {
+        Self::new("GetServer")?.with_utf(server)
+    }
+
+    /// Appends a u16-length-prefixed string. Java uses modified UTF-8 here, which only
+    /// differs from plain UTF-8 for NUL and characters outside the BMP.
+    pub fn with_utf(mut self, value: &str) -> Result<Self> {
+        let length = u16::try_from(value.len())
+            .map_err(|_| anyhow!("BungeeCord message field is too long ({} bytes)", value.len()))?;
+        self.data.extend_from_slice(&length.to_be_bytes());
+        self.data.extend_from_slice(value.as_bytes());
+        Ok(self)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Wraps the message in a play-state Plugin Message packet.
+    pub fn into_packet(self, protocol_version: i32, ids: &PlayIds) -> Vec<u8> {
+        PacketBuilder::new(ids.plugin_message)
+            .with_string(Self::channel(protocol_version))
+            .with_raw_bytes(&self.data)
+            .build()
+    }
+}
+
+/// Reads a string written with `writeUTF`, see [`BungeeMessage::with_utf`].
+fn read_utf(reader: &mut Cursor<&[u8]>) -> Result<String> {
+    let mut length = [0u8; 2];
+    reader.read_exact(&mut length)?;
+    let mut value = vec![0u8; u16::from_be_bytes(length) as usize];
+    reader
+        .read_exact(&mut value)
+        .map_err(|_| anyhow!("BungeeCord message field runs past the end of the message"))?;
+    Ok(String::from_utf8(value)?)
+}
+
+/// Answers a message sent on the BungeeCord channel the way the proxy would, with
+/// `server` as the name of this server. `None` for sub-channels the limbo doesn't answer.
+pub fn answer(data: &[u8], server: &str) -> Result<Option<BungeeMessage>> {
+    let mut reader = Cursor::new(data);
+    match read_utf(&mut reader)?.as_str() {
+        "GetServer" => Ok(Some(BungeeMessage::get_server(server)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Plugin message asking the proxy to move the player to `server`.
+pub fn bungee_connect_packet(server: &str, protocol_version: i32, ids: &PlayIds) -> Result<Vec<u8>> {
+    Ok(BungeeMessage::connect(server)?.into_packet(protocol_version, ids))
+}
@@ -0,0 +1,68 @@
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use uuid::Uuid;
+
+use super::{read_string, varint::VarInt};
+
+/// 1.19, which added the optional chat signing key to Login Start.
+const SIGNATURE_DATA_MIN_PROTOCOL: i32 = 759;
+/// 1.19.1, which added the optional UUID.
+const UUID_MIN_PROTOCOL: i32 = 760;
+/// 1.19.3, which dropped the signing key again.
+const SIGNATURE_DATA_MAX_PROTOCOL: i32 = 760;
+/// 1.20.2, since which the UUID is always there.
+const REQUIRED_UUID_MIN_PROTOCOL: i32 = 764;
+
+/// Longest key and signature vanilla accepts, in bytes.
+const MAX_PUBLIC_KEY_LENGTH: i32 = 512;
+const MAX_SIGNATURE_LENGTH: i32 = 4096;
+
+/// Login Start, the first packet of the login state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginStart {
+    pub username: String,
+    /// The UUID the client believes it has, sent from 1.19.1 on. It isn't checked by
+    /// anyone, so only forwarded player information should be trusted.
+    pub uuid: Option<Uuid>,
+}
+
+impl LoginStart {
+    /// Reads the fields Login Start has in `protocol_version`. The chat signing key 1.19
+    /// and 1.19.1 clients may send is skipped.
+    pub async fn read(reader: &mut (impl AsyncRead + std::marker::Unpin), protocol_version: i32) -> Result<Self> {
+        let username = read_string(reader).await?;
+
+        if (SIGNATURE_DATA_MIN_PROTOCOL..=SIGNATURE_DATA_MAX_PROTOCOL).contains(&protocol_version)
+            && reader.read_u8().await? != 0
+        {
+            let _expires_at = reader.read_i64().await?;
+            skip_byte_array(reader, MAX_PUBLIC_KEY_LENGTH).await?;
+            skip_byte_array(reader, MAX_SIGNATURE_LENGTH).await?;
+        }
+
+        let has_uuid = match protocol_version {
+            v if v >= REQUIRED_UUID_MIN_PROTOCOL => true,
+            v if v >= UUID_MIN_PROTOCOL => reader.read_u8().await? != 0,
+            _ => false,
+        };
+        let uuid = match has_uuid {
+            true => Some(Uuid::from_u128(reader.read_u128().await?)),
+            false => None,
+        };
+
+        Ok(Self { username, uuid })
+    }
+}
+
+async fn skip_byte_array(reader: &mut (impl AsyncRead + std::marker::Unpin), max_length: i32) -> Result<()> {
+    let length = VarInt::read(reader).await?.into_inner();
+    if !(0..=max_length).contains(&length) {
+        return Err(anyhow!("Login Start has a signature field of invalid length {length}"));
+    }
+
+    let skipped = tokio::io::copy(&mut reader.take(length as u64), &mut tokio::io::sink()).await?;
+    if skipped != length as u64 {
+        return Err(anyhow!("Login Start ends in the middle of its signature data"));
+    }
+    Ok(())
+}
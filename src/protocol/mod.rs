@@ -1,21 +1,110 @@
+use std::fmt::Display;
+
 use anyhow::Result;
 use tokio::io::{AsyncRead, AsyncWrite};
-// use tokio_byteorder::{AsyncReadBytesExt, AsyncWriteBytesExt, BigEndian};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use varint::VarInt;
 
 pub mod varint;
 pub mod packet;
+pub mod login;
+pub mod play;
+pub mod plugin;
+pub mod bungee;
+pub mod bitset;
+pub mod proxy;
+pub mod settings;
 
-pub async fn read_generic_packet(reader: &mut (impl AsyncRead + std::marker::Unpin)) -> Result<(i32, Vec<u8>)> {
-    let length = VarInt::read(reader).await?.into_inner();
+/// Protocol version in the handshake of clients that only want the server list entry and
+/// don't know which version the server runs, like server list sites and ping tools.
+pub const STATUS_ONLY_PROTOCOL: i32 = -1;
+/// Largest packet vanilla accepts, the most a 3-byte VarInt length can describe.
+pub const MAX_PACKET_LENGTH: i32 = 2097151;
+/// Longest string the protocol allows, in bytes: 32767 UTF-16 code units of up to 3 bytes.
+const MAX_STRING_LENGTH: i32 = 32767 * 3;
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// The connection closed between packets, which is how clients normally leave.
+    Closed,
+    /// The connection closed, or a packet ended, before all of a packet's fields were read.
+    UnexpectedEof,
+    VarIntTooBig,
+    StringNotUtf8,
+    PacketTooLarge(i32),
+    /// A negative length, or a string longer than the protocol allows.
+    InvalidLength(i32),
+    UnknownPacket { state: i32, id: i32 },
+    Io(std::io::Error),
+}
+
+impl Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::Closed => write!(f, "connection closed"),
+            ProtocolError::UnexpectedEof => write!(f, "unexpected end of stream"),
+            ProtocolError::VarIntTooBig => write!(f, "VarInt is too big"),
+            ProtocolError::StringNotUtf8 => write!(f, "string is not valid UTF-8"),
+            ProtocolError::PacketTooLarge(length) => write!(f, "packet of {length} bytes is too large"),
+            ProtocolError::InvalidLength(length) => write!(f, "invalid length {length}"),
+            ProtocolError::UnknownPacket { state, id } => write!(f, "unknown packet {id:#04x} in state {state}"),
+            ProtocolError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProtocolError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ProtocolError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => ProtocolError::UnexpectedEof,
+            _ => ProtocolError::Io(e),
+        }
+    }
+}
+
+pub async fn read_generic_packet(
+    reader: &mut (impl AsyncRead + std::marker::Unpin),
+) -> Result<(i32, Vec<u8>), ProtocolError> {
+    let Some(length) = VarInt::read_optional(reader).await? else {
+        return Err(ProtocolError::Closed);
+    };
+    let length = length.into_inner();
+    if length > MAX_PACKET_LENGTH {
+        return Err(ProtocolError::PacketTooLarge(length));
+    }
     let packet_id = VarInt::read(reader).await?;
     let length = length - packet_id.length() as i32;
+    if length < 0 {
+        return Err(ProtocolError::InvalidLength(length));
+    }
     let mut buffer = vec![0; length as usize];
     reader.read_exact(&mut buffer).await?;
     Ok((packet_id.into_inner(), buffer))
 }
 
+/// Writes a framed packet, such as a built [`packet::PacketBuilder`], and flushes it.
+pub async fn send_packet(writer: &mut (impl AsyncWrite + std::marker::Unpin), packet: impl Into<Vec<u8>>) -> Result<()> {
+    write_packet(writer, packet).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Writes a framed packet without flushing, so a buffered writer can send several in one
+/// go. The caller flushes once the batch is complete.
+pub async fn write_packet(writer: &mut (impl AsyncWrite + std::marker::Unpin), packet: impl Into<Vec<u8>>) -> Result<()> {
+    writer.write_all(&packet.into()).await?;
+    Ok(())
+}
+
 pub async fn write_generic_packet(writer: &mut (impl AsyncWrite + std::marker::Unpin), packet_id: i32, buffer: &[u8]) -> Result<()> {
     let length = VarInt::new((VarInt::new(packet_id).length() + buffer.len()) as i32);
     length.write(writer).await?;
@@ -24,11 +113,20 @@ pub async fn write_generic_packet(writer: &mut (impl AsyncWrite + std::marker::U
     Ok(())
 }
 
-pub async fn read_string(reader: &mut (impl AsyncRead + std::marker::Unpin)) -> Result<String> {
+/// Reads a VarInt-prefixed string. The buffer only grows with the bytes actually there, so
+/// a length running past the end of the packet is rejected without allocating for it.
+pub async fn read_string(reader: &mut (impl AsyncRead + std::marker::Unpin)) -> Result<String, ProtocolError> {
     let length = VarInt::read(reader).await?.into_inner();
-    let mut buffer = vec![0; length as usize];
-    reader.read_exact(&mut buffer).await?;
-    Ok(String::from_utf8(buffer)?)
+    if !(0..=MAX_STRING_LENGTH).contains(&length) {
+        return Err(ProtocolError::InvalidLength(length));
+    }
+
+    let mut buffer = Vec::new();
+    reader.take(length as u64).read_to_end(&mut buffer).await?;
+    if buffer.len() != length as usize {
+        return Err(ProtocolError::InvalidLength(length));
+    }
+    String::from_utf8(buffer).map_err(|_| ProtocolError::StringNotUtf8)
 }
 
 pub async fn write_string(writer: &mut (impl AsyncWrite + std::marker::Unpin), string: &str) -> Result<()> {
@@ -36,4 +134,12 @@ pub async fn write_string(writer: &mut (impl AsyncWrite + std::marker::Unpin), s
     length.write(writer).await?;
     writer.write_all(string.as_bytes()).await?;
     Ok(())
-}
\ No newline at end of file
+}
+/// Forge clients append an FML marker to the handshake server address, separated by a
+/// null byte: `\0FML\0` for 1.12 and older, `\0FML2\0` and `\0FML3\0` for newer versions.
+pub fn is_fml_address(server_address: &str) -> bool {
+    server_address
+        .split('\0')
+        .skip(1)
+        .any(|marker| matches!(marker, "FML" | "FML2" | "FML3"))
+}
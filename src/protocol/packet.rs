@@ -1,7 +1,16 @@
+use std::ops::RangeInclusive;
+
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+
 use crate::nbt::NamedTag;
 
 use super::varint::VarInt;
 
+/// X and Z a packed block position can hold, 26-bit two's complement.
+const POSITION_XZ: RangeInclusive<i64> = -(1 << 25)..=(1 << 25) - 1;
+/// Y a packed block position can hold, 12-bit two's complement.
+const POSITION_Y: RangeInclusive<i64> = -(1 << 11)..=(1 << 11) - 1;
 
 pub struct PacketBuilder {
     pub id: i32,
@@ -16,26 +25,19 @@ impl PacketBuilder {
         }
     }
 
-    pub fn with_var_int(mut self, mut value: i32) -> Self {
-        loop {
-            let mut byte = (value & 0b01111111) as u8;
-            value >>= 7;
-            if value != 0 {
-                byte |= 0b10000000;
-            }
-            self.buffer.push(byte);
-            if value == 0 {
-                break;
-            }
-        }
-        self
+    pub fn with_var_int(self, value: i32) -> Self {
+        self.with_raw_bytes(&VarInt::from(value).to_bytes())
     }
 
-
     pub fn with_uuid(self, value: u128) -> Self {
         self.with_raw_bytes(&value.to_be_bytes())
     }
 
+    pub fn with_uuid_value(self, value: Uuid) -> Self {
+        // Uuid stores its bytes in network (big-endian) order already
+        self.with_raw_bytes(value.as_bytes())
+    }
+
     pub fn with_string(self, value: &str) -> Self {
         // let mut pkt = self.with_var_int(value.len() as i32);
         // pkt.buffer.extend_from_slice(value.as_bytes());
@@ -59,6 +61,21 @@ impl PacketBuilder {
         self
     }
 
+    pub fn with_i8(mut self, value: i8) -> Self {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn with_u32(mut self, value: u32) -> Self {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn with_u16(mut self, value: u16) -> Self {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
     pub fn with_u8(mut self, value: u8) -> Self {
         self.buffer.push(value);
         self
@@ -84,15 +101,43 @@ impl PacketBuilder {
         self
     }
 
+    /// Writes whether `value` is present, followed by the value itself if it is.
+    pub fn with_optional<T>(self, value: Option<T>, write: impl FnOnce(Self, T) -> Self) -> Self {
+        match value {
+            Some(value) => write(self.with_bool(true), value),
+            None => self.with_bool(false),
+        }
+    }
+
+    /// Writes a VarInt element count followed by each element.
+    pub fn with_prefixed_array<T>(self, values: &[T], write: impl Fn(Self, &T) -> Self) -> Self {
+        values
+            .iter()
+            .fold(self.with_var_int(values.len() as i32), write)
+    }
+
+    /// Writes a bit set as a VarInt long count followed by each long, see [`BitSet`](super::bitset::BitSet).
+    pub fn with_bitset(self, longs: &[u64]) -> Self {
+        self.with_prefixed_array(longs, |p, long| p.with_i64(*long as i64))
+    }
+
     pub fn with_raw_bytes(mut self, value: &[u8]) -> Self {
         self.buffer.extend_from_slice(value);
         self
     }
 
-    pub fn with_position(mut self, x: i64, y: i64, z: i64) -> Self {
-        let value = (x.to_be() & 0x3FFFFFF_i64.to_be()) | (z.to_be() & 0x3FFFFFF_i64.to_be()) << 26 | (y.to_be() & 0xFFF_i64.to_be()) << 52;
-        self.buffer.extend_from_slice(&value.to_ne_bytes());
-        self
+    /// Block position packed into a long, x and z in 26 bits each and y in 12. Coordinates
+    /// outside that range wrap, so untrusted ones should go through [`Self::try_with_position`].
+    pub fn with_position(self, x: i64, y: i64, z: i64) -> Self {
+        self.with_i64((x & 0x3FFFFFF) << 38 | (z & 0x3FFFFFF) << 12 | (y & 0xFFF))
+    }
+
+    /// Like [`Self::with_position`], but fails instead of wrapping coordinates that don't fit.
+    pub fn try_with_position(self, x: i64, y: i64, z: i64) -> Result<Self> {
+        if !POSITION_XZ.contains(&x) || !POSITION_Y.contains(&y) || !POSITION_XZ.contains(&z) {
+            return Err(anyhow!("Block position {x}, {y}, {z} is out of range"));
+        }
+        Ok(self.with_position(x, y, z))
     }
 
     pub fn build(self) -> Vec<u8> {
@@ -106,8 +151,8 @@ impl PacketBuilder {
     }
 }
 
-impl Into<Vec<u8>> for PacketBuilder {
-    fn into(self) -> Vec<u8> {
-        self.build()
+impl From<PacketBuilder> for Vec<u8> {
+    fn from(packet: PacketBuilder) -> Vec<u8> {
+        packet.build()
     }
 }
\ No newline at end of file
@@ -0,0 +1,78 @@
+use std::ops::RangeInclusive;
+
+/// Play packet IDs, which vanilla renumbers in almost every release. Every protocol a
+/// registry codec is embedded for needs an entry in [`PLAY_IDS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayIds {
+    // Clientbound
+    pub set_container_slot: i32,
+    pub plugin_message: i32,
+    pub disconnect: i32,
+    pub entity_event: i32,
+    pub keep_alive: i32,
+    pub chunk_data: i32,
+    pub join_game: i32,
+    pub map_data: i32,
+    pub player_abilities: i32,
+    pub player_info: i32,
+    pub synchronize_position: i32,
+    pub resource_pack: i32,
+    pub set_held_item: i32,
+    pub set_center_chunk: i32,
+    pub set_title_text: i32,
+    pub system_chat: i32,
+    pub update_recipes: i32,
+    pub update_tags: i32,
+
+    // Serverbound
+    pub chat_command: i32,
+    pub client_information: i32,
+    pub serverbound_plugin_message: i32,
+    pub serverbound_keep_alive: i32,
+    pub set_player_position: i32,
+    pub set_player_position_and_rotation: i32,
+    pub resource_pack_status: i32,
+    pub swing_arm: i32,
+}
+
+impl PlayIds {
+    pub const V1_19_2: PlayIds = PlayIds {
+        set_container_slot: 0x13,
+        plugin_message: 0x16,
+        disconnect: 0x19,
+        entity_event: 0x1a,
+        keep_alive: 0x20,
+        chunk_data: 0x21,
+        join_game: 0x25,
+        map_data: 0x26,
+        player_abilities: 0x31,
+        player_info: 0x37,
+        synchronize_position: 0x39,
+        resource_pack: 0x3d,
+        set_held_item: 0x4a,
+        set_center_chunk: 0x4b,
+        set_title_text: 0x5d,
+        system_chat: 0x62,
+        update_recipes: 0x6a,
+        update_tags: 0x6b,
+
+        chat_command: 0x04,
+        client_information: 0x08,
+        serverbound_plugin_message: 0x0d,
+        serverbound_keep_alive: 0x12,
+        set_player_position: 0x14,
+        set_player_position_and_rotation: 0x15,
+        resource_pack_status: 0x24,
+        swing_arm: 0x2f,
+    };
+
+    pub fn for_protocol(protocol_version: i32) -> Option<&'static PlayIds> {
+        PLAY_IDS
+            .iter()
+            .find(|(protocols, _)| protocols.contains(&protocol_version))
+            .map(|(_, ids)| ids)
+    }
+}
+
+/// Play packet IDs by the client protocol versions they are valid for.
+pub const PLAY_IDS: &[(RangeInclusive<i32>, PlayIds)] = &[(760..=760, PlayIds::V1_19_2)];
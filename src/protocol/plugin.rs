@@ -0,0 +1,17 @@
+use super::packet::PacketBuilder;
+use super::play::PlayIds;
+
+/// Channel the server and client announce their brand on, shown on the F3 screen.
+pub const BRAND_CHANNEL: &str = "minecraft:brand";
+/// Brand the limbo reports. Proxies usually show it after their own, e.g.
+/// `Velocity (void-rs)`.
+pub const SERVER_BRAND: &str = "void-rs";
+
+/// Plugin Message announcing `brand` on [`BRAND_CHANNEL`]. The payload is a
+/// length-prefixed string like any other.
+pub fn brand_packet(ids: &PlayIds, brand: &str) -> Vec<u8> {
+    PacketBuilder::new(ids.plugin_message)
+        .with_string(BRAND_CHANNEL)
+        .with_string(brand)
+        .build()
+}
@@ -0,0 +1,60 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::anyhow;
+use anyhow::Result;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Every PROXY protocol v2 header starts with this signature.
+pub const SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+const COMMAND_LOCAL: u8 = 0x0;
+const COMMAND_PROXY: u8 = 0x1;
+const FAMILY_TCP4: u8 = 0x11;
+const FAMILY_TCP6: u8 = 0x21;
+
+/// Reads a PROXY protocol v2 header and returns the original client address. Returns `None`
+/// for LOCAL connections (e.g. the balancer's own health checks) and for address families
+/// other than TCP over IPv4/IPv6, which carry no usable address.
+pub async fn read_header(reader: &mut (impl AsyncRead + std::marker::Unpin)) -> Result<Option<SocketAddr>> {
+    let mut header = [0u8; 16];
+    reader.read_exact(&mut header).await?;
+
+    if header[..12] != SIGNATURE {
+        return Err(anyhow!("Missing PROXY protocol v2 signature"));
+    }
+    if header[12] >> 4 != 2 {
+        return Err(anyhow!("Unsupported PROXY protocol version {}", header[12] >> 4));
+    }
+
+    let command = header[12] & 0x0f;
+    let family = header[13];
+    let length = u16::from_be_bytes([header[14], header[15]]) as usize;
+
+    // Always consume the whole header, including any TLVs after the addresses
+    let mut addresses = vec![0u8; length];
+    reader.read_exact(&mut addresses).await?;
+
+    match command {
+        COMMAND_LOCAL => return Ok(None),
+        COMMAND_PROXY => {}
+        _ => return Err(anyhow!("Unknown PROXY protocol command {}", command)),
+    }
+
+    let (ip, port_offset) = match family {
+        FAMILY_TCP4 if length >= 12 => {
+            let octets: [u8; 4] = addresses[..4].try_into()?;
+            (IpAddr::V4(Ipv4Addr::from(octets)), 8)
+        }
+        FAMILY_TCP6 if length >= 36 => {
+            let octets: [u8; 16] = addresses[..16].try_into()?;
+            (IpAddr::V6(Ipv6Addr::from(octets)), 32)
+        }
+        FAMILY_TCP4 | FAMILY_TCP6 => {
+            return Err(anyhow!("PROXY protocol address block too short ({} bytes)", length))
+        }
+        _ => return Ok(None),
+    };
+
+    let port = u16::from_be_bytes([addresses[port_offset], addresses[port_offset + 1]]);
+    Ok(Some(SocketAddr::new(ip, port)))
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{read_string, varint::VarInt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatMode {
+    Enabled,
+    CommandsOnly,
+    Hidden,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MainHand {
+    Left,
+    Right,
+}
+
+/// What the client reports about itself in Client Information. Clients send it once
+/// after joining and again whenever the player changes their options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientSettings {
+    /// e.g. `en_us`.
+    pub locale: String,
+    pub view_distance: i8,
+    pub chat_mode: ChatMode,
+    pub chat_colors: bool,
+    /// Bit mask of the visible skin layers (cape, jacket, sleeves, pants, hat).
+    pub skin_parts: u8,
+    pub main_hand: MainHand,
+    pub text_filtering: bool,
+    pub allow_server_listings: bool,
+}
+
+impl Default for ClientSettings {
+    /// What the vanilla client sends with its options untouched.
+    fn default() -> Self {
+        Self {
+            locale: String::from("en_us"),
+            view_distance: 10,
+            chat_mode: ChatMode::Enabled,
+            chat_colors: true,
+            skin_parts: 0x7f,
+            main_hand: MainHand::Right,
+            text_filtering: false,
+            allow_server_listings: true,
+        }
+    }
+}
+
+impl ClientSettings {
+    pub async fn read(reader: &mut (impl AsyncRead + std::marker::Unpin)) -> Result<Self> {
+        let locale = read_string(reader).await?;
+        let view_distance = reader.read_i8().await?;
+        // Unknown values fall back to the defaults rather than dropping the player
+        let chat_mode = match VarInt::read(reader).await?.into_inner() {
+            1 => ChatMode::CommandsOnly,
+            2 => ChatMode::Hidden,
+            _ => ChatMode::Enabled,
+        };
+        let chat_colors = reader.read_u8().await? != 0;
+        let skin_parts = reader.read_u8().await?;
+        let main_hand = match VarInt::read(reader).await?.into_inner() {
+            0 => MainHand::Left,
+            _ => MainHand::Right,
+        };
+        let text_filtering = reader.read_u8().await? != 0;
+        let allow_server_listings = reader.read_u8().await? != 0;
+
+        Ok(Self {
+            locale,
+            view_distance,
+            chat_mode,
+            chat_colors,
+            skin_parts,
+            main_hand,
+            text_filtering,
+            allow_server_listings,
+        })
+    }
+}
@@ -1,8 +1,10 @@
 use std::fmt::Display;
 
-use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use anyhow::Result;
 
+use super::ProtocolError;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct VarInt {
     pub value: i32,
@@ -35,28 +37,65 @@ impl VarInt {
         self.value
     }
 
-    pub async fn read(reader: &mut (impl AsyncRead + std::marker::Unpin)) -> Result<Self> {
-        let mut value = 0;
-        let mut position = 0;
+    /// Adds one encoded byte to a partially decoded value. Returns the value once the last
+    /// byte has been seen.
+    fn decode_byte(value: &mut i32, position: &mut u32, byte: u8) -> Result<Option<i32>, ProtocolError> {
+        *value |= ((byte & 0x7F) as i32) << *position;
+        if (byte & 0x80) == 0 {
+            return Ok(Some(*value));
+        }
+        *position += 7;
+        if *position >= 32 {
+            return Err(ProtocolError::VarIntTooBig);
+        }
+        Ok(None)
+    }
+
+    pub async fn read(reader: &mut (impl AsyncRead + std::marker::Unpin)) -> Result<Self, ProtocolError> {
+        let (mut value, mut position) = (0, 0);
 
         loop {
-            let byte = reader.read_u8().await? as i32;
-            value |= ((byte & 0x7F) as i32) << position;
-            if (byte & 0x80) == 0 {
-                break;
+            let byte = reader.read_u8().await?;
+            if let Some(value) = Self::decode_byte(&mut value, &mut position, byte)? {
+                return Ok(Self::new(value));
             }
-            position += 7;
-            if position >= 32 {
-                return Err(anyhow::anyhow!("VarInt is too big"));
+        }
+    }
+
+    /// Like [`VarInt::read`], but a stream that ends before the first byte gives `None`
+    /// rather than an error.
+    pub async fn read_optional(reader: &mut (impl AsyncRead + std::marker::Unpin)) -> Result<Option<Self>, ProtocolError> {
+        let (mut value, mut position) = (0, 0);
+
+        loop {
+            let byte = match reader.read_u8().await {
+                Ok(byte) => byte,
+                Err(e) if position == 0 && e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            if let Some(value) = Self::decode_byte(&mut value, &mut position, byte)? {
+                return Ok(Some(Self::new(value)));
             }
         }
+    }
+
+    pub fn read_sync(reader: &mut impl std::io::Read) -> Result<Self, ProtocolError> {
+        let (mut value, mut position) = (0, 0);
 
-        Ok(Self::new(value))
+        loop {
+            let mut byte = [0u8];
+            reader.read_exact(&mut byte)?;
+            if let Some(value) = Self::decode_byte(&mut value, &mut position, byte[0])? {
+                return Ok(Self::new(value));
+            }
+        }
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut value = self.value;
-        let mut bytes = Vec::new();
+        // Work on the bits as unsigned, so negative numbers take 5 bytes instead of
+        // sign-extending forever
+        let mut value = self.value as u32;
+        let mut bytes = Vec::with_capacity(5);
 
         loop {
             if (value & !0x7F) == 0 {
@@ -72,34 +111,22 @@ impl VarInt {
     }
 
     pub async fn write(&self, writer: &mut (impl AsyncWrite + std::marker::Unpin)) -> Result<()> {
-        let mut value = self.value;
-
-        loop {
-            if (value & !0x7F) == 0 {
-                writer.write_u8(value as u8).await?;
-                break;
-            } else {
-                writer.write_u8((value & 0x7F | 0x80) as u8).await?;
-                value >>= 7;
-            }
-        }
+        writer.write_all(&self.to_bytes()).await?;
+        Ok(())
+    }
 
+    pub fn write_sync(&self, writer: &mut impl std::io::Write) -> Result<()> {
+        writer.write_all(&self.to_bytes())?;
         Ok(())
     }
 
     pub fn length(&self) -> usize {
-        let mut value = self.value;
-        let mut length = 0;
-
-        loop {
-            length += 1;
-            if (value & !0x7F) == 0 {
-                break;
-            } else {
-                value >>= 7;
-            }
+        match self.value as u32 {
+            0..=0x7F => 1,
+            0x80..=0x3FFF => 2,
+            0x4000..=0x1F_FFFF => 3,
+            0x20_0000..=0xFFF_FFFF => 4,
+            _ => 5,
         }
-
-        length
     }
 }
\ No newline at end of file
@@ -0,0 +1,142 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::watch;
+
+/// Connection slots shared by every listener, and the line of connections waiting for one
+/// once they are all taken. A freed slot goes straight to the front of the line, so waiting
+/// connections are let in first come, first served.
+pub struct ConnectionQueue {
+    line: Mutex<Line>,
+    /// Notified whenever the line moves, so waiting connections can show their new position.
+    moved: watch::Sender<()>,
+}
+
+struct Line {
+    free_slots: usize,
+    max_waiting: usize,
+    waiting: VecDeque<u64>,
+    /// Tickets a slot has been handed to, until they claim it.
+    admitted: HashSet<u64>,
+    next_ticket: u64,
+}
+
+impl Line {
+    fn release_slot(&mut self) {
+        match self.waiting.pop_front() {
+            Some(ticket) => {
+                self.admitted.insert(ticket);
+            }
+            None => self.free_slots += 1,
+        }
+    }
+}
+
+impl ConnectionQueue {
+    /// `slots` connections are let in at once and `max_waiting` more may wait for a slot. 0
+    /// slots means no limit, and a `max_waiting` of 0 disables the queue.
+    pub fn new(slots: usize, max_waiting: usize) -> Self {
+        let line = Line {
+            free_slots: if slots == 0 { usize::MAX } else { slots },
+            max_waiting,
+            waiting: VecDeque::new(),
+            admitted: HashSet::new(),
+            next_ticket: 0,
+        };
+        Self {
+            line: Mutex::new(line),
+            moved: watch::Sender::new(()),
+        }
+    }
+
+    /// Takes a free slot, if there is one.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<Slot> {
+        let mut line = self.line.lock().unwrap();
+        if line.free_slots == 0 {
+            return None;
+        }
+        line.free_slots -= 1;
+        Some(Slot(Arc::clone(self)))
+    }
+
+    /// Joins the back of the line, unless it is already full.
+    pub fn enqueue(self: &Arc<Self>) -> Option<Ticket> {
+        let mut line = self.line.lock().unwrap();
+        if line.waiting.len() >= line.max_waiting {
+            return None;
+        }
+
+        let id = line.next_ticket;
+        line.next_ticket += 1;
+        line.waiting.push_back(id);
+        Some(Ticket {
+            id,
+            queue: Arc::clone(self),
+            moved: self.moved.subscribe(),
+        })
+    }
+
+    /// Connections waiting for a slot.
+    pub fn waiting(&self) -> usize {
+        self.line.lock().unwrap().waiting.len()
+    }
+}
+
+/// A connection slot, handed to the next connection in line when dropped.
+pub struct Slot(Arc<ConnectionQueue>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.line.lock().unwrap().release_slot();
+        self.0.moved.send_replace(());
+    }
+}
+
+/// A place in line. Dropping it leaves the line, or passes on the slot it was handed.
+pub struct Ticket {
+    id: u64,
+    queue: Arc<ConnectionQueue>,
+    moved: watch::Receiver<()>,
+}
+
+impl Ticket {
+    /// Position in line counting from 1, or `None` once a slot is waiting to be claimed.
+    pub fn position(&self) -> Option<usize> {
+        let line = self.queue.line.lock().unwrap();
+        line.waiting.iter().position(|&id| id == self.id).map(|index| index + 1)
+    }
+
+    /// Claims the slot this ticket has been handed, or gives the ticket back if it is still
+    /// waiting.
+    pub fn admit(self) -> Result<Slot, Ticket> {
+        let admitted = self.queue.line.lock().unwrap().admitted.remove(&self.id);
+        match admitted {
+            true => Ok(Slot(Arc::clone(&self.queue))),
+            false => Err(self),
+        }
+    }
+
+    /// Waits for the line to move, after which the position may have changed or a slot may
+    /// be ready.
+    pub async fn moved(&mut self) {
+        if self.moved.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut line = self.queue.line.lock().unwrap();
+        if line.admitted.remove(&self.id) {
+            line.release_slot();
+        } else if let Some(index) = line.waiting.iter().position(|&id| id == self.id) {
+            line.waiting.remove(index);
+        } else {
+            // Claimed by `admit`, which passed the slot on
+            return;
+        }
+        drop(line);
+        self.queue.moved.send_replace(());
+    }
+}
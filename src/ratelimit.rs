@@ -0,0 +1,151 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Sliding-window limit on how many connections a single IP may open.
+pub struct ConnectionLimiter {
+    max_connections: usize,
+    window: Duration,
+    recent: HashMap<IpAddr, VecDeque<Instant>>,
+    last_prune: Instant,
+}
+
+impl ConnectionLimiter {
+    /// Allows at most `max_connections` per IP within `window`. A limit of 0 disables it.
+    pub fn new(max_connections: usize, window: Duration) -> Self {
+        Self {
+            max_connections,
+            window,
+            recent: HashMap::new(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    /// Changes the limit, e.g. after a config reload. Recorded attempts are kept.
+    pub fn set_limit(&mut self, max_connections: usize, window: Duration) {
+        self.max_connections = max_connections;
+        self.window = window;
+    }
+
+    /// Records a connection attempt from `ip` and returns whether it is allowed.
+    pub fn allow(&mut self, ip: IpAddr, now: Instant) -> bool {
+        if self.max_connections == 0 {
+            return true;
+        }
+
+        if now.saturating_duration_since(self.last_prune) >= self.window {
+            self.prune(now);
+        }
+
+        let window = self.window;
+        let attempts = self.recent.entry(ip).or_default();
+        while attempts
+            .front()
+            .is_some_and(|&at| now.saturating_duration_since(at) >= window)
+        {
+            attempts.pop_front();
+        }
+
+        if attempts.len() >= self.max_connections {
+            return false;
+        }
+
+        attempts.push_back(now);
+        true
+    }
+
+    /// Forgets IPs with no attempts inside the window, so the map doesn't grow without bound.
+    fn prune(&mut self, now: Instant) {
+        let window = self.window;
+        self.recent.retain(|_, attempts| {
+            attempts
+                .back()
+                .is_some_and(|&at| now.saturating_duration_since(at) < window)
+        });
+        self.last_prune = now;
+    }
+}
+
+/// Token bucket limiting how many packets a single connection may send. It refills at
+/// `rate` tokens per second up to `burst`, so short bursts like joining pass.
+pub struct PacketLimiter {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl PacketLimiter {
+    /// A rate of 0 disables the limit.
+    pub fn new(rate: u32, burst: u32, now: Instant) -> Self {
+        let burst = f64::from(burst.max(rate));
+        Self {
+            rate: f64::from(rate),
+            burst,
+            tokens: burst,
+            last: now,
+        }
+    }
+
+    /// Takes a token for a packet received at `now` and returns whether there was one.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        if self.rate == 0.0 {
+            return true;
+        }
+
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Minimum time between two registrations from the same address, to slow down mass account
+/// creation. Keyed by the address string since forwarded addresses aren't always IPs.
+pub struct RegistrationCooldown {
+    cooldown: Duration,
+    last: HashMap<String, Instant>,
+    last_prune: Instant,
+}
+
+impl RegistrationCooldown {
+    /// A cooldown of zero disables it.
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            last: HashMap::new(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    pub fn set_cooldown(&mut self, cooldown: Duration) {
+        self.cooldown = cooldown;
+    }
+
+    /// How long `address` still has to wait before registering again, if at all.
+    pub fn remaining(&self, address: &str, now: Instant) -> Option<Duration> {
+        let at = self.last.get(address)?;
+        let remaining = self.cooldown.saturating_sub(now.saturating_duration_since(*at));
+        (!remaining.is_zero()).then_some(remaining)
+    }
+
+    /// Records a successful registration from `address`.
+    pub fn record(&mut self, address: &str, now: Instant) {
+        if self.cooldown.is_zero() {
+            return;
+        }
+
+        if now.saturating_duration_since(self.last_prune) >= self.cooldown {
+            let cooldown = self.cooldown;
+            self.last.retain(|_, at| now.saturating_duration_since(*at) < cooldown);
+            self.last_prune = now;
+        }
+
+        self.last.insert(address.to_string(), now);
+    }
+}
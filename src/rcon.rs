@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+
+use crate::{players, Context};
+
+pub const TYPE_RESPONSE: i32 = 0;
+pub const TYPE_COMMAND: i32 = 2;
+pub const TYPE_AUTH_RESPONSE: i32 = 2;
+pub const TYPE_AUTH: i32 = 3;
+
+/// Request ID the server answers an auth packet with when the password is wrong.
+const AUTH_FAILED_ID: i32 = -1;
+/// Length of an RCON packet without its body: request id, type and two terminating nulls.
+const HEADER_LENGTH: i32 = 10;
+const MAX_BODY_LENGTH: i32 = 4096;
+
+/// A packet in Valve's Source RCON protocol, which Minecraft uses. All integers are little-endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RconPacket {
+    pub id: i32,
+    pub kind: i32,
+    pub body: String,
+}
+
+impl RconPacket {
+    pub fn new(id: i32, kind: i32, body: impl Into<String>) -> Self {
+        Self {
+            id,
+            kind,
+            body: body.into(),
+        }
+    }
+
+    pub async fn read(reader: &mut (impl AsyncRead + std::marker::Unpin)) -> Result<Self> {
+        let length = reader.read_i32_le().await?;
+        if !(HEADER_LENGTH..=HEADER_LENGTH + MAX_BODY_LENGTH).contains(&length) {
+            return Err(anyhow!("Invalid RCON packet length {length}"));
+        }
+
+        let id = reader.read_i32_le().await?;
+        let kind = reader.read_i32_le().await?;
+
+        let mut body = vec![0; (length - 8) as usize];
+        reader.read_exact(&mut body).await?;
+        if body.pop() != Some(0) || body.pop() != Some(0) {
+            return Err(anyhow!("RCON packet is not null terminated"));
+        }
+
+        Ok(Self {
+            id,
+            kind,
+            body: String::from_utf8(body)?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let length = HEADER_LENGTH + self.body.len() as i32;
+        let mut out = Vec::with_capacity(length as usize + 4);
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&self.id.to_le_bytes());
+        out.extend_from_slice(&self.kind.to_le_bytes());
+        out.extend_from_slice(self.body.as_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out
+    }
+}
+
+pub async fn serve(bind: String, password: String, context: Arc<Mutex<Context>>) -> Result<()> {
+    let listener = TcpListener::bind(&bind).await?;
+    log::info!("RCON listening on {}", bind);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let password = password.clone();
+        let context = Arc::clone(&context);
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(stream, &password, context).await {
+                log::debug!("RCON connection from {} closed: {:?}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_client(mut stream: TcpStream, password: &str, context: Arc<Mutex<Context>>) -> Result<()> {
+    let peer = stream.peer_addr()?;
+    let mut authenticated = false;
+
+    loop {
+        let packet = RconPacket::read(&mut stream).await?;
+
+        match packet.kind {
+            TYPE_AUTH => {
+                authenticated = packet.body == password;
+                let id = if authenticated { packet.id } else { AUTH_FAILED_ID };
+                stream
+                    .write_all(&RconPacket::new(id, TYPE_AUTH_RESPONSE, "").to_bytes())
+                    .await?;
+
+                if !authenticated {
+                    log::warn!("Failed RCON login from {}", peer);
+                    return Ok(());
+                }
+                log::info!("RCON login from {}", peer);
+            }
+            TYPE_COMMAND if authenticated => {
+                log::info!("RCON [{}] issued command: {}", peer, packet.body);
+                let response = execute(&packet.body, &context).await;
+                stream
+                    .write_all(&RconPacket::new(packet.id, TYPE_RESPONSE, response).to_bytes())
+                    .await?;
+            }
+            _ => {
+                stream
+                    .write_all(&RconPacket::new(AUTH_FAILED_ID, TYPE_AUTH_RESPONSE, "").to_bytes())
+                    .await?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Runs an RCON command and returns the text sent back to the client.
+pub async fn execute(command: &str, context: &Mutex<Context>) -> String {
+    let (name, args) = command.trim().split_once(' ').unwrap_or((command.trim(), ""));
+    let args = args.trim();
+
+    match name {
+        "list" => {
+            let players = Arc::clone(&context.lock().await.players);
+            let names = players::online(&players).await.into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+            format!("There are {} players online: {}", names.len(), names.join(", "))
+        }
+        "ban" if !args.is_empty() => {
+            let db = Arc::clone(&context.lock().await.db);
+            if let Err(e) = db.ban(args).await {
+                log::error!("Database error: {:?}", e);
+                return String::from("Database error.");
+            }
+
+            let context = context.lock().await;
+            if let Some(player) = context.players.lock().await.get(args) {
+                player.disconnect(&context.config.get().messages.fallback().banned);
+            }
+            format!("Banned {args}.")
+        }
+        "kick" if !args.is_empty() => {
+            let (target, reason) = args.split_once(' ').unwrap_or((args, "Kicked by an operator."));
+            let players = Arc::clone(&context.lock().await.players);
+            let player = players.lock().await.get(target).cloned();
+            match player {
+                Some(player) => {
+                    player.disconnect(reason);
+                    format!("Kicked {target}.")
+                }
+                None => format!("{target} is not online."),
+            }
+        }
+        "whitelist" => {
+            let (action, name) = match args.split_once(' ') {
+                Some((action @ ("add" | "remove"), name)) => (action, name.trim()),
+                _ => return String::from("Usage: whitelist add|remove <name>"),
+            };
+
+            let db = Arc::clone(&context.lock().await.db);
+            let result = match action {
+                "add" => db.whitelist_add(name).await,
+                _ => db.whitelist_remove(name).await,
+            };
+            match result {
+                Ok(()) => format!("Whitelist updated ({action} {name})."),
+                Err(e) => {
+                    log::error!("Database error: {:?}", e);
+                    String::from("Database error.")
+                }
+            }
+        }
+        "broadcast" if !args.is_empty() => {
+            let players = Arc::clone(&context.lock().await.players);
+            let count = players::broadcast(&players, args).await;
+            format!("Broadcast to {count} players.")
+        }
+        "reload" => match context.lock().await.reload_config() {
+            Ok(()) => String::from("Config reloaded. It applies to new connections."),
+            Err(e) => {
+                log::error!("Failed to reload the config: {:?}", e);
+                format!("The config couldn't be loaded: {e}")
+            }
+        },
+        _ => String::from(
+            "Commands: list, ban <name>, kick <name> [reason], whitelist add|remove <name>, broadcast <message>, reload",
+        ),
+    }
+}
@@ -0,0 +1,86 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::anyhow;
+use anyhow::Result;
+use data_encoding::{BASE32_NOPAD, HEXLOWER};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// RFC 6238 time step.
+pub const STEP_SECS: u64 = 30;
+const DIGITS: usize = 6;
+const SECRET_LEN: usize = 20;
+const NONCE_LEN: usize = 12;
+
+pub fn generate_secret() -> Vec<u8> {
+    rand::random::<[u8; SECRET_LEN]>().to_vec()
+}
+
+/// RFC 4226 HOTP value for `counter`, truncated to six digits.
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    (binary & 0x7fff_ffff) % 10u32.pow(DIGITS as u32)
+}
+
+/// The code an authenticator app shows at `unix_time`.
+pub fn code_at(secret: &[u8], unix_time: u64) -> u32 {
+    hotp(secret, unix_time / STEP_SECS)
+}
+
+/// Checks a six digit code against the current step and one step either side, to allow
+/// for clock drift between the server and the player's device.
+pub fn verify(secret: &[u8], code: &str, unix_time: u64) -> bool {
+    if code.len() != DIGITS || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    let Ok(code) = code.parse::<u32>() else {
+        return false;
+    };
+
+    let step = unix_time / STEP_SECS;
+    [step.checked_sub(1), Some(step), step.checked_add(1)]
+        .into_iter()
+        .flatten()
+        .any(|counter| hotp(secret, counter) == code)
+}
+
+/// Base32 form of the secret, as typed into authenticator apps.
+pub fn encode_secret(secret: &[u8]) -> String {
+    BASE32_NOPAD.encode(secret)
+}
+
+/// Key used to encrypt stored secrets, derived from the configured passphrase.
+pub fn derive_key(passphrase: &str) -> [u8; 32] {
+    Sha256::digest(passphrase.as_bytes()).into()
+}
+
+/// Encrypts a secret for storage as hex of `nonce || ciphertext`.
+pub fn encrypt_secret(key: &[u8; 32], secret: &[u8]) -> Result<String> {
+    let cipher = Aes256Gcm::new(key.into());
+    let nonce = rand::random::<[u8; NONCE_LEN]>();
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), secret)
+        .map_err(|_| anyhow!("Failed to encrypt TOTP secret"))?;
+
+    let mut stored = nonce.to_vec();
+    stored.extend_from_slice(&ciphertext);
+    Ok(HEXLOWER.encode(&stored))
+}
+
+pub fn decrypt_secret(key: &[u8; 32], stored: &str) -> Result<Vec<u8>> {
+    let stored = HEXLOWER.decode(stored.as_bytes())?;
+    if stored.len() < NONCE_LEN {
+        return Err(anyhow!("Stored TOTP secret is truncated"));
+    }
+
+    let (nonce, ciphertext) = stored.split_at(NONCE_LEN);
+    Aes256Gcm::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("Failed to decrypt TOTP secret, was the encryption key changed?"))
+}
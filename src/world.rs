@@ -0,0 +1,167 @@
+use crate::config::PlatformConfig;
+use crate::nbt::CompoundBuilder;
+use crate::protocol::packet::PacketBuilder;
+use crate::protocol::play::PlayIds;
+use crate::protocol::varint::VarInt;
+
+/// The limbo is in the End, which is 256 blocks high starting at Y 0.
+const SECTIONS: i32 = 16;
+const AIR: i32 = 0;
+/// Bits per entry once a section has too many block states for a palette of its own.
+const GLOBAL_PALETTE_BITS: u32 = 15;
+/// Biomes of every section, taken from an empty chunk.
+const BIOMES: [u8; 13] = [0x01, 0x02, 0x27, 0x03, 0x01, 0xCC, 0xFF, 0xCC, 0xFF, 0xCC, 0xFF, 0xCC, 0xFF];
+
+/// The blocks of the limbo: the configured platforms, with air everywhere else.
+pub struct World {
+    platforms: Vec<PlatformConfig>,
+}
+
+impl World {
+    pub fn new(platforms: &[PlatformConfig]) -> Self {
+        Self {
+            platforms: platforms.to_vec(),
+        }
+    }
+
+    /// Where platforms overlap, the one configured last wins.
+    fn block_at(&self, x: i32, y: i32, z: i32) -> i32 {
+        self.platforms
+            .iter()
+            .rev()
+            .find(|platform| {
+                (platform.x..platform.x + platform.width).contains(&x)
+                    && (platform.y..platform.y + platform.height).contains(&y)
+                    && (platform.z..platform.z + platform.length).contains(&z)
+            })
+            .map_or(AIR, |platform| platform.block)
+    }
+
+    /// Block states of a 16×16×16 section, ordered by Y, then Z, then X. `None` if no
+    /// platform reaches into it.
+    fn section(&self, cx: i32, sy: i32, cz: i32) -> Option<Vec<i32>> {
+        let (x0, y0, z0) = (cx * 16, sy * 16, cz * 16);
+        let touched = self.platforms.iter().any(|platform| {
+            platform.x < x0 + 16
+                && platform.x + platform.width > x0
+                && platform.y < y0 + 16
+                && platform.y + platform.height > y0
+                && platform.z < z0 + 16
+                && platform.z + platform.length > z0
+        });
+        if !touched {
+            return None;
+        }
+
+        let mut blocks = Vec::with_capacity(4096);
+        for y in y0..y0 + 16 {
+            for z in z0..z0 + 16 {
+                for x in x0..x0 + 16 {
+                    blocks.push(self.block_at(x, y, z));
+                }
+            }
+        }
+        Some(blocks)
+    }
+}
+
+/// Chunk coordinates within `view_distance` chunks of spawn, row by row.
+pub fn chunks_around_spawn(view_distance: i32) -> impl Iterator<Item = (i32, i32)> {
+    (-view_distance..=view_distance)
+        .flat_map(move |cx| (-view_distance..=view_distance).map(move |cz| (cx, cz)))
+}
+
+/// Chunk Data and Update Light for the chunk at `cx`, `cz`, without any light. The
+/// sections are written to `data` first, which is cleared, so one buffer can be reused
+/// for every chunk.
+pub fn build_chunk_packet(ids: &PlayIds, cx: i32, cz: i32, world: &World, data: &mut Vec<u8>) -> Vec<u8> {
+    data.clear();
+    for sy in 0..SECTIONS {
+        match world.section(cx, sy, cz) {
+            Some(blocks) => {
+                let count = blocks.iter().filter(|&&block| block != AIR).count();
+                data.extend_from_slice(&(count as i16).to_be_bytes());
+                write_block_states(data, &blocks);
+            }
+            None => {
+                data.extend_from_slice(&0i16.to_be_bytes());
+                write_single_value(data, AIR);
+            }
+        }
+        data.extend_from_slice(&BIOMES);
+    }
+
+    PacketBuilder::new(ids.chunk_data)
+        .with_i32(cx)
+        .with_i32(cz)
+        .with_nbt(
+            &CompoundBuilder::new()
+                .long_array("MOTION_BLOCKING", vec![0; 36])
+                .build(),
+        )
+        .with_var_int(data.len() as i32) // size of data
+        .with_raw_bytes(data)
+        .with_var_int(0) // no. of block entities
+        .with_bool(true) // trust edges for light updates
+        .with_bitset(&[]) // sky light mask
+        .with_bitset(&[]) // block light mask
+        .with_bitset(&[]) // empty sky light mask
+        .with_bitset(&[]) // empty block light mask
+        .with_var_int(0) // no. of sky lights
+        .with_var_int(0) // no. of block lights
+        .build()
+}
+
+fn write_single_value(data: &mut Vec<u8>, block: i32) {
+    data.push(0); // bits per entry
+    data.extend_from_slice(&VarInt::from(block).to_bytes());
+    data.push(0); // data array length
+}
+
+/// Writes a paletted container of 4096 block states, using a palette of the section's
+/// own for up to 256 different states and global IDs beyond that.
+fn write_block_states(data: &mut Vec<u8>, blocks: &[i32]) {
+    let mut palette = Vec::new();
+    for block in blocks {
+        if !palette.contains(block) {
+            palette.push(*block);
+        }
+    }
+    if palette.len() == 1 {
+        return write_single_value(data, palette[0]);
+    }
+
+    let bits = (usize::BITS - (palette.len() - 1).leading_zeros()).max(4);
+    let (bits, values) = if bits <= 8 {
+        data.push(bits as u8);
+        data.extend_from_slice(&VarInt::from(palette.len() as i32).to_bytes());
+        for block in &palette {
+            data.extend_from_slice(&VarInt::from(*block).to_bytes());
+        }
+        let indices = blocks
+            .iter()
+            .map(|block| palette.iter().position(|entry| entry == block).unwrap_or(0) as u64)
+            .collect::<Vec<_>>();
+        (bits, indices)
+    } else {
+        data.push(GLOBAL_PALETTE_BITS as u8);
+        (GLOBAL_PALETTE_BITS, blocks.iter().map(|&block| block as u64).collect())
+    };
+
+    // Entries don't span longs, so any leftover high bits stay unused
+    let per_long = (64 / bits) as usize;
+    let longs = values
+        .chunks(per_long)
+        .map(|entries| {
+            entries
+                .iter()
+                .enumerate()
+                .fold(0u64, |long, (i, &value)| long | value << (i as u32 * bits))
+        })
+        .collect::<Vec<_>>();
+
+    data.extend_from_slice(&VarInt::from(longs.len() as i32).to_bytes());
+    for long in longs {
+        data.extend_from_slice(&long.to_be_bytes());
+    }
+}